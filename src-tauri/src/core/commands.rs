@@ -1,20 +1,37 @@
-use futures::FutureExt;
 use rdkafka::{Offset, Timestamp};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fmt::Display;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use tauri::{AppHandle, Emitter, State};
-use tokio::sync::mpsc;
-use tokio::time::sleep;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::time::{self, sleep, MissedTickBehavior};
 
 use crate::core::config::{ApplicationState, ClusterConfig};
+use crate::core::error::AppError;
+use crate::core::secrets::{SecretRef, SecretStoreStatus};
 
 use crate::kafka::admin::{self, get_topic_partition_offsets, get_topics_offsets, ConfigProperty};
+use crate::kafka::deserializer::{Decoded, MessageDeserializers, TopicFormats};
+use crate::kafka::export::{self, ExportFormat, ExportWriter};
+use crate::kafka::filter::MessageFilter;
+use crate::kafka::health::{self, ConnectionTestReport};
+use crate::kafka::properties::{self, ExportReport, ImportReport, PropertiesFormat};
 use crate::kafka::consumer::{
-  ConsumerGroup, ConsumerGroupOffsetDescription, KafkaConsumer, MessageEnvelope,
+  ConsumedRecord, ConsumerControl, ConsumerError, ConsumerGroup, ConsumerGroupOffsetDescription, ConsumerSummary,
+  DeliveryOptions, FetchOffset, KafkaConsumer, MessageEnvelope, PartitionOffset, ScanProgress, StopConditions, StopReason,
+  SCAN_PROGRESS_INTERVAL,
+};
+use crate::kafka::metadata::{ClusterMetadata, MetadataChanges};
+use crate::kafka::message::{DisplayFormat, MessageBytes};
+use crate::kafka::producer::{DeliveryReport, ProduceRequest, ProducerRecord};
+use crate::kafka::replay::{self, ReplayFormat, ReplayOptions, ReplaySummary};
+use crate::kafka::session::{ConsumerSession, SessionKind, SessionState};
+use crate::kafka::schema_registry::{
+  CompatibilityCheck, CompatibilityLevel, RegisteredSchema, SchemaDefinition, SchemaDiff, SchemaRegistryClient,
+  SchemaTarget, TopicSubjects,
 };
-use crate::kafka::metadata::ClusterMetadata;
 use crate::kafka::util::TopicOffsetsMap;
 
 #[tauri::command]
@@ -22,32 +39,302 @@ pub fn get_current_cluster(app_config: State<ApplicationState>) -> ClusterConfig
   app_config.config.lock().unwrap().default_cluster_config()
 }
 
+#[tauri::command]
+pub fn list_clusters(app_config: State<ApplicationState>) -> Result<Vec<ClusterConfig>, AppError> {
+  app_config
+  .config
+  .lock()
+  .map(|config| config.clusters())
+  .map_err(AppError::from)
+}
+
+#[tauri::command]
+pub fn set_current_cluster(
+  app_handle: AppHandle,
+  app_config: State<ApplicationState>,
+  name: &str,
+) -> Result<ClusterConfig, AppError> {
+  let (previous, current) = {
+    let mut config = app_config.config.lock()?;
+    let previous = config.default_cluster_config();
+    (previous, config.set_default_cluster(name)?)
+  };
+
+  if previous.name != current.name {
+    app_config.reset_cluster(&previous.name)?;
+    app_handle
+    .emit("cluster_changed", current.clone())?;
+  }
+  Ok(current)
+}
+
+#[tauri::command]
+pub fn add_cluster(
+  app_config: State<ApplicationState>,
+  cluster: ClusterConfig,
+) -> Result<ClusterConfig, AppError> {
+  app_config
+  .config
+  .lock()?
+  .add_cluster(cluster)
+}
+
+#[tauri::command]
+pub fn update_cluster(
+  app_config: State<ApplicationState>,
+  cluster: ClusterConfig,
+) -> Result<ClusterConfig, AppError> {
+  app_config
+  .config
+  .lock()?
+  .update_cluster(cluster)
+  .inspect(|updated| app_config.clients.remove(&updated.name))
+}
+
+#[tauri::command]
+pub fn rename_cluster(
+  app_config: State<ApplicationState>,
+  name: &str,
+  new_name: &str,
+) -> Result<ClusterConfig, AppError> {
+  let renamed = app_config
+  .config
+  .lock()?
+  .rename_cluster(name, new_name)?;
+
+  // Running consumers, cached metadata and clients are tracked by the old name.
+  app_config.reset_cluster(name)?;
+  app_config.clients.remove(name);
+  Ok(renamed)
+}
+
+#[tauri::command]
+pub fn delete_cluster(
+  app_handle: AppHandle,
+  app_config: State<ApplicationState>,
+  name: &str,
+) -> Result<ClusterConfig, AppError> {
+  let (removed, previous, current) = {
+    let mut config = app_config.config.lock()?;
+    let previous = config.default_cluster_config();
+    let removed = config.remove_cluster(name)?;
+    (removed, previous, config.default_cluster_config())
+  };
+
+  app_config.reset_cluster(name)?;
+  app_config.clients.remove(name);
+  if previous.name != current.name {
+    app_handle
+    .emit("cluster_changed", current)?;
+  }
+  Ok(removed)
+}
+
+/// Checks a cluster before it is saved, `cluster` does not need to exist in the config.
 #[tauri::command(async)]
-pub fn get_topics(app_config: State<ApplicationState>) -> Result<ClusterMetadata, String> {
-  KafkaConsumer::connect(
-    app_config
-    .config
-    .lock()
-    .unwrap()
-    .default_cluster_config()
-    .bootstrap_servers,
-  )
-  .get_metadata()
+pub fn test_cluster_connection(
+  app_config: State<ApplicationState>,
+  cluster: ClusterConfig,
+) -> Result<ConnectionTestReport, AppError> {
+  cluster.validate()?;
+  Ok(health::test_connection(&cluster, &app_config.secrets))
+}
+
+/// Adds a cluster from a Java `client.properties` or a kcat config file.
+#[tauri::command(async)]
+pub fn import_cluster_properties(
+  app_config: State<ApplicationState>,
+  path: &str,
+  name: &str,
+) -> Result<ImportReport, AppError> {
+  let content = std::fs::read_to_string(path)
+  .map_err(|err| AppError::Io(format!("Could not read '{}': {}", path, err)))?;
+  let report = properties::import_cluster(name, properties::parse_properties(&content), &app_config.secrets)?;
+
+  let added = app_config
+  .config
+  .lock()?
+  .add_cluster(report.cluster.clone());
+  if let Err(err) = added {
+    properties::remove_imported_secrets(&report.cluster, &app_config.secrets);
+    return Err(err);
+  }
+  Ok(report)
+}
+
+/// Writes a cluster as a properties file for the CLI tools, secrets are written to
+/// the file and never returned to the frontend.
+#[tauri::command(async)]
+pub fn export_cluster_properties(
+  app_config: State<ApplicationState>,
+  name: &str,
+  format: PropertiesFormat,
+  path: &str,
+) -> Result<ExportReport, AppError> {
+  let cluster = app_config
+  .config
+  .lock()?
+  .clusters()
+  .into_iter()
+  .find(|cluster| cluster.name == name)
+  .ok_or_else(|| AppError::invalid(format!("Cluster key '{name}' does not exist in the config")))?;
+
+  let (content, skipped) = properties::export_cluster(&cluster, format, &app_config.secrets)?;
+  std::fs::write(path, content).map_err(|err| AppError::Io(format!("Could not write '{}': {}", path, err)))?;
+
+  Ok(ExportReport { path: path.to_owned(), skipped })
+}
+
+#[tauri::command]
+pub fn get_secrets_status(app_config: State<ApplicationState>) -> Result<SecretStoreStatus, AppError> {
+  app_config.secrets.status()
+}
+
+#[tauri::command(async)]
+pub fn unlock_secrets(app_config: State<ApplicationState>, passphrase: &str) -> Result<(), AppError> {
+  app_config.secrets.unlock(passphrase)
+}
+
+/// Secret changes invalidate every pooled client, they hold the credentials resolved
+/// when they were created.
+#[tauri::command]
+pub fn lock_secrets(app_config: State<ApplicationState>) -> Result<(), AppError> {
+  app_config.secrets.lock()?;
+  app_config.clients.clear();
+  Ok(())
+}
+
+#[tauri::command(async)]
+pub fn change_secrets_passphrase(
+  app_config: State<ApplicationState>,
+  passphrase: &str,
+  new_passphrase: &str,
+) -> Result<(), AppError> {
+  app_config.secrets.change_passphrase(passphrase, new_passphrase)?;
+  app_config.clients.clear();
+  Ok(())
+}
+
+/// Stores a credential and returns the reference to put in the cluster config,
+/// the value itself is never handed back to the frontend.
+#[tauri::command]
+pub fn store_secret(
+  app_config: State<ApplicationState>,
+  id: Option<SecretRef>,
+  value: &str,
+) -> Result<SecretRef, AppError> {
+  let id = app_config.secrets.put(id, value)?;
+  app_config.clients.clear();
+  Ok(id)
+}
+
+#[tauri::command]
+pub fn delete_secret(app_config: State<ApplicationState>, id: SecretRef) -> Result<(), AppError> {
+  app_config.secrets.remove(&id)?;
+  app_config.clients.clear();
+  Ok(())
+}
+
+/// Fetches the metadata of `cluster`, caches it and emits `metadata_changed` when it
+/// differs from the previously cached metadata.
+fn fetch_cluster_metadata(
+  app_handle: &AppHandle,
+  app_config: &ApplicationState,
+  cluster: &ClusterConfig,
+) -> Result<ClusterMetadata, AppError> {
+  let metadata = app_config
+  .clients
+  .get(cluster, &app_config.secrets)?
+  .consumer
+  .fetch_metadata()?;
+
+  if let Some(changes) = app_config.metadata.update(&cluster.name, metadata.clone())? {
+    // Views miss the changes until their next fetch, the metadata itself is fine.
+    if let Err(err) = app_handle.emit::<MetadataChanges>("metadata_changed", changes) {
+      eprintln!("Could not emit the metadata changes of cluster `{}`: {}", cluster.name, err);
+    }
+  }
+  Ok(metadata)
+}
+
+#[tauri::command(async)]
+pub async fn get_topics(
+  app_handle: AppHandle,
+  app_config: State<'_, ApplicationState>,
+) -> Result<ClusterMetadata, AppError> {
+  let (cluster, ttl) = {
+    let config = app_config.config.lock().unwrap();
+    (config.default_cluster_config(), config.metadata_ttl())
+  };
+
+  let metadata = match app_config.metadata.get_fresh(&cluster.name, ttl) {
+    Some(metadata) => metadata,
+    None => fetch_cluster_metadata(&app_handle, &app_config, &cluster)?,
+  };
+  Ok(link_subjects(&app_config, &cluster, metadata, ttl).await)
+}
+
+/// Refetches the metadata of the current cluster, bypassing the cache.
+#[tauri::command(async)]
+pub async fn refresh_metadata(
+  app_handle: AppHandle,
+  app_config: State<'_, ApplicationState>,
+) -> Result<ClusterMetadata, AppError> {
+  let (cluster, ttl) = {
+    let config = app_config.config.lock().unwrap();
+    (config.default_cluster_config(), config.metadata_ttl())
+  };
+
+  let metadata = fetch_cluster_metadata(&app_handle, &app_config, &cluster)?;
+  Ok(link_subjects(&app_config, &cluster, metadata, ttl).await)
+}
+
+/// Links every topic to its `-key` and `-value` subjects. Topics are still listed when the
+/// registry can not be reached, without subjects.
+async fn link_subjects(
+  app_config: &ApplicationState,
+  cluster: &ClusterConfig,
+  mut metadata: ClusterMetadata,
+  ttl: Duration,
+) -> ClusterMetadata {
+  let subjects = match app_config.clients.schema_registry(cluster, &app_config.secrets) {
+    Ok(Some(registry)) => registry.cached_subjects(ttl).await,
+    Ok(None) => return metadata,
+    Err(err) => Err(err),
+  };
+  match subjects {
+    Ok(subjects) => {
+      for topic in metadata.topics.iter_mut() {
+        topic.subjects = TopicSubjects::for_topic(&topic.name, &subjects);
+      }
+    },
+    Err(err) => eprintln!("Could not list the subjects of cluster `{}`: {}", cluster.name, err),
+  }
+  metadata
+}
+
+#[tauri::command]
+pub fn set_metadata_ttl(app_config: State<ApplicationState>, seconds: u64) -> Result<(), AppError> {
+  app_config
+  .config
+  .lock()?
+  .set_metadata_ttl(seconds)
 }
 
 #[tauri::command(async)]
 pub async fn fetch_topic_configs(
   app_config: State<'_, ApplicationState>,
   topics: Vec<String>,
-) -> Result<HashMap<String, Vec<ConfigProperty>>, String> {
-  let bootstrap_servers = app_config
+  overrides: Option<HashMap<String, String>>,
+) -> Result<HashMap<String, Vec<ConfigProperty>>, AppError> {
+  let cluster = app_config
     .config
     .lock()
     .unwrap()
-    .default_cluster_config()
-    .bootstrap_servers;
+    .default_cluster_config();
+  let admin = app_config.clients.admin(&cluster, &app_config.secrets, overrides)?;
   
-  admin::get_topic_configs(bootstrap_servers, topics).await
+  admin::get_topic_configs(&admin, topics).await
 }
 
 #[tauri::command(async)]
@@ -55,61 +342,182 @@ pub async fn alter_topic_configs(
   app_config: State<'_, ApplicationState>,
   topic: &str,
   configs: HashMap<&str, &str>,
-) -> Result<(), String> {
-  let bootstrap_servers = app_config
+  overrides: Option<HashMap<String, String>>,
+) -> Result<(), AppError> {
+  let cluster = app_config
   .config
   .lock()
   .unwrap()
-  .default_cluster_config()
-  .bootstrap_servers;
+  .default_cluster_config();
+  let admin = app_config.clients.admin(&cluster, &app_config.secrets, overrides)?;
   println!("Topic: {}, configs: {:?}", topic, configs);
-  admin::alter_topic_configs(bootstrap_servers, topic, configs).await
+  admin::alter_topic_configs(&admin, topic, configs).await
+}
+
+/// The schema registry of the current cluster.
+fn current_schema_registry(app_config: &ApplicationState) -> Result<Arc<SchemaRegistryClient>, AppError> {
+  let cluster = app_config
+  .config
+  .lock()
+  .unwrap()
+  .default_cluster_config();
+  app_config
+  .clients
+  .schema_registry(&cluster, &app_config.secrets)?
+  .ok_or_else(|| AppError::invalid(format!("Cluster '{}' has no schema registry configured", cluster.name)))
+}
+
+#[tauri::command(async)]
+pub async fn list_subjects(
+  app_config: State<'_, ApplicationState>,
+  deleted: Option<bool>,
+) -> Result<Vec<String>, AppError> {
+  current_schema_registry(&app_config)?
+  .subjects(deleted.unwrap_or(false))
+  .await
+}
+
+#[tauri::command(async)]
+pub async fn list_subject_versions(
+  app_config: State<'_, ApplicationState>,
+  subject: &str,
+  deleted: Option<bool>,
+) -> Result<Vec<i32>, AppError> {
+  current_schema_registry(&app_config)?
+  .versions(subject, deleted.unwrap_or(false))
+  .await
+}
+
+/// `version` is a version number or `latest`, the default.
+#[tauri::command(async)]
+pub async fn get_subject_schema(
+  app_config: State<'_, ApplicationState>,
+  subject: &str,
+  version: Option<&str>,
+) -> Result<RegisteredSchema, AppError> {
+  current_schema_registry(&app_config)?
+  .subject_version(subject, version.unwrap_or("latest"))
+  .await
+}
+
+/// Returns the id of the registered schema.
+#[tauri::command(async)]
+pub async fn register_schema(
+  app_config: State<'_, ApplicationState>,
+  subject: &str,
+  schema: SchemaDefinition,
+) -> Result<u32, AppError> {
+  current_schema_registry(&app_config)?
+  .register(subject, &schema)
+  .await
+}
+
+#[tauri::command(async)]
+pub async fn check_schema_compatibility(
+  app_config: State<'_, ApplicationState>,
+  subject: &str,
+  schema: SchemaDefinition,
+  version: Option<&str>,
+) -> Result<CompatibilityCheck, AppError> {
+  current_schema_registry(&app_config)?
+  .check_compatibility(subject, version.unwrap_or("latest"), &schema)
+  .await
+}
+
+/// The level of `subject`, or the global level without one.
+#[tauri::command(async)]
+pub async fn get_compatibility_level(
+  app_config: State<'_, ApplicationState>,
+  subject: Option<&str>,
+) -> Result<CompatibilityLevel, AppError> {
+  current_schema_registry(&app_config)?
+  .compatibility(subject)
+  .await
+}
+
+#[tauri::command(async)]
+pub async fn set_compatibility_level(
+  app_config: State<'_, ApplicationState>,
+  subject: Option<&str>,
+  level: CompatibilityLevel,
+) -> Result<CompatibilityLevel, AppError> {
+  current_schema_registry(&app_config)?
+  .set_compatibility(subject, level)
+  .await
+}
+
+/// Soft deletes a version, or removes it for good with `permanent`. Returns the deleted version.
+#[tauri::command(async)]
+pub async fn delete_schema_version(
+  app_config: State<'_, ApplicationState>,
+  subject: &str,
+  version: &str,
+  permanent: Option<bool>,
+) -> Result<i32, AppError> {
+  current_schema_registry(&app_config)?
+  .delete_version(subject, version, permanent.unwrap_or(false))
+  .await
+}
+
+#[tauri::command(async)]
+pub async fn diff_schema_versions(
+  app_config: State<'_, ApplicationState>,
+  subject: &str,
+  from: &str,
+  to: &str,
+) -> Result<SchemaDiff, AppError> {
+  current_schema_registry(&app_config)?
+  .diff(subject, from, to)
+  .await
 }
 
 #[tauri::command(async)]
 pub async fn delete_topic(
   app_config: State<'_, ApplicationState>,
   topic: &str,
-) -> Result<String, String> {
-  let bootstrap_servers = app_config
+  overrides: Option<HashMap<String, String>>,
+) -> Result<String, AppError> {
+  let cluster = app_config
   .config
   .lock()
   .unwrap()
-  .default_cluster_config()
-  .bootstrap_servers;
+  .default_cluster_config();
+  let admin = app_config.clients.admin(&cluster, &app_config.secrets, overrides)?;
   
-  admin::delete_topic(bootstrap_servers, topic).await
+  let result = admin::delete_topic(&admin, topic).await;
+  app_config.metadata.invalidate(&cluster.name);
+  result
 }
 
 #[tauri::command(async)]
 pub fn get_group_offsets(
   app_config: State<ApplicationState>,
   group_name: String,
-) -> Result<Vec<ConsumerGroupOffsetDescription>, String> {
-  let servers = app_config
+) -> Result<Vec<ConsumerGroupOffsetDescription>, AppError> {
+  let cluster = app_config
   .config
   .lock()
   .unwrap()
-  .default_cluster_config()
-  .bootstrap_servers;
+  .default_cluster_config();
   
-  KafkaConsumer::connect_config(HashMap::from([
-    ("bootstrap.servers".to_owned(), servers.join(",")),
+  KafkaConsumer::connect_config(&cluster, &app_config.secrets, HashMap::from([
     ("group.id".to_owned(), group_name.clone()),
-    ]))
+    ]))?
     .get_committed_offsets()
   }
   
   #[tauri::command(async)]
-  pub fn get_groups(app_config: State<ApplicationState>) -> Result<Vec<ConsumerGroup>, String> {
-    KafkaConsumer::connect(
-      app_config
-      .config
-      .lock()
-      .unwrap()
-      .default_cluster_config()
-      .bootstrap_servers,
-    )
+  pub fn get_groups(app_config: State<ApplicationState>) -> Result<Vec<ConsumerGroup>, AppError> {
+    let cluster = app_config
+    .config
+    .lock()
+    .unwrap()
+    .default_cluster_config();
+
+    app_config
+    .clients
+    .get(&cluster, &app_config.secrets)?
+    .consumer
     .get_groups_list()
   }
   
@@ -120,27 +528,25 @@ pub fn get_group_offsets(
     partitions: i32,
     replication: i32,
     configs: HashMap<String, String>,
-  ) -> Result<String, String> {
-    let bootstrap_servers = app_config
+    overrides: Option<HashMap<String, String>>,
+  ) -> Result<String, AppError> {
+    let cluster = app_config
       .config
       .lock()
       .unwrap()
-      .default_cluster_config()
-      .bootstrap_servers;
+      .default_cluster_config();
+    let admin = app_config.clients.admin(&cluster, &app_config.secrets, overrides)?;
 
     let result = admin::create_topic(
-      bootstrap_servers,
+      &admin,
       topic,
       partitions,
       replication,
       configs,
       None,
     ).await;
-    
-    match result {
-      Ok(out) => Ok(out),
-      Err((data, _err_code)) => Err(data),
-    }
+    app_config.metadata.invalidate(&cluster.name);
+    result
   }
   
   #[derive(Serialize, Deserialize)]
@@ -162,102 +568,263 @@ pub fn get_group_offsets(
     }
   }
   
-  #[derive(Serialize, Deserialize, Clone)]
-  #[serde(tag = "type", content = "content")]
-  pub enum FetchOffset {
-    Beginning,
-    End,
-    Timestamp(i64),
-  }
-  impl Display for FetchOffset {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-      match self {
-        Self::Beginning => f.write_str("Beginning"),
-        Self::End => f.write_str("End"),
-        Self::Timestamp(t) => f.write_fmt(format_args!("Timestamp({})", t)),
-      }
-    }
-  }
-  impl Into<Offset> for FetchOffset {
-    fn into(self) -> Offset {
-      match self {
-        Self::End => Offset::End,
-        Self::Beginning => Offset::Beginning,
-        Self::Timestamp(timestamp) => Offset::Offset(timestamp),
-      }
-    }
+  /// Renders the bytes of a received key, payload or header value in another display format.
+  #[tauri::command]
+  pub fn render_message_bytes(bytes: MessageBytes, format: DisplayFormat) -> Option<String> {
+    bytes.render(format)
   }
-  
+
   #[tauri::command(async)]
   pub async fn create_group_offsets(
     app_config: State<'_, ApplicationState>,
     group_id: &str,
     topics: Vec<&str>,
     initial_offset: GroupOffset,
-  ) -> Result<(), String> {
-    let servers = app_config
+    overrides: Option<HashMap<String, String>>,
+  ) -> Result<(), AppError> {
+    let cluster = app_config
     .config
     .lock()
     .unwrap()
     .default_cluster_config()
-    .bootstrap_servers;
+    .with_overrides(overrides)?;
     
-    admin::create_consumer_group(servers, group_id, topics, initial_offset).await
+    admin::create_consumer_group(&cluster, &app_config.secrets, group_id, topics, initial_offset).await
   }
   
   #[tauri::command(async)]
   pub async fn delete_consumer_group(
     app_config: State<'_, ApplicationState>,
     group: &str,
-  ) -> Result<String, String> {
-    let bootstrap_servers = app_config
+    overrides: Option<HashMap<String, String>>,
+  ) -> Result<String, AppError> {
+    let cluster = app_config
     .config
     .lock()
     .unwrap()
-    .default_cluster_config()
-    .bootstrap_servers;
+    .default_cluster_config();
+    let admin = app_config.clients.admin(&cluster, &app_config.secrets, overrides)?;
     
-    admin::delete_consumer_group(bootstrap_servers, group).await
+    admin::delete_consumer_group(&admin, group).await
   }
   
+  /// Sends one message and waits for its delivery report.
+  #[tauri::command(async)]
+  pub async fn produce_message(
+    app_state: State<'_, ApplicationState>,
+    request: ProduceRequest,
+    overrides: Option<HashMap<String, String>>,
+  ) -> Result<DeliveryReport, AppError> {
+    let cluster = app_state
+    .config
+    .lock()
+    .unwrap()
+    .default_cluster_config();
+    let producer = app_state
+    .clients
+    .producer(&cluster, &app_state.secrets, request.settings, overrides)?;
+
+    let mut record = ProducerRecord::from(&request);
+    if request.key_schema.is_some() || request.value_schema.is_some() {
+      let registry = current_schema_registry(&app_state)?;
+      if let (Some(target), Some(key)) = (&request.key_schema, &request.key) {
+        record.key = Some(encode_with_schema(&registry, target, key).await.map_err(|err| err.context("Key"))?);
+      }
+      if let (Some(target), Some(value)) = (&request.value_schema, &request.value) {
+        record.value = Some(encode_with_schema(&registry, target, value).await.map_err(|err| err.context("Value"))?);
+      }
+    }
+    producer.send(&record).await
+  }
+
+  async fn encode_with_schema(
+    registry: &SchemaRegistryClient,
+    target: &SchemaTarget,
+    json: &str,
+  ) -> Result<Vec<u8>, AppError> {
+    let document = serde_json::from_str(json).map_err(|err| AppError::invalid(format!("Not valid JSON: {}", err)))?;
+    registry.encode(target, &document).await
+  }
+
+  /// Produces every record of a file, emitting `produce_progress` while sending.
+  /// Partitions and timestamps read from the file are only sent with `preserve_partitions` and
+  /// `preserve_timestamps`, CSV partition and timestamp columns are rejected without them.
+  #[tauri::command(async)]
+  pub async fn produce_from_file(
+    app_handle: AppHandle,
+    app_state: State<'_, ApplicationState>,
+    path: String,
+    topic: String,
+    format: ReplayFormat,
+    options: Option<ReplayOptions>,
+    overrides: Option<HashMap<String, String>>,
+  ) -> Result<ReplaySummary, AppError> {
+    let options = options.unwrap_or_default();
+    let cluster = app_state
+    .config
+    .lock()
+    .unwrap()
+    .default_cluster_config();
+    let producer = app_state
+    .clients
+    .producer(&cluster, &app_state.secrets, options.settings, overrides)?;
+
+    replay::produce_from_file(producer, Path::new(&path), &topic, &format, &options, |progress| {
+      let _ = app_handle.emit("produce_progress", progress);
+    }).await
+  }
+
+  /// Writes the messages of `topic` between `start` and `end`, the current end when unset, to `path`.
+  /// Emits `<export id>/progress` while writing and the `ExportSummary` on `<export id>/done`,
+  /// the export can be stopped like a consumer through `stop_consumer`.
+  #[tauri::command(async)]
+  pub async fn export_topic_range(
+    app_handle: AppHandle,
+    app_state: State<'_, ApplicationState>,
+    topic: &str,
+    start: FetchOffset,
+    end: Option<FetchOffset>,
+    path: String,
+    format: ExportFormat,
+    overrides: Option<HashMap<String, String>>,
+  ) -> Result<(String, TopicOffsetsMap), AppError> {
+    let cluster = app_state
+    .config
+    .lock()
+    .unwrap()
+    .default_cluster_config()
+    .with_overrides(overrides)?;
+    let mut stream = KafkaConsumer::connect_bounded(&cluster, &app_state.secrets)?;
+
+    let end_offsets = export::end_offsets(&stream, topic, end.clone().unwrap_or(FetchOffset::End).into())?;
+    let offsets_map = stream
+    .assign_offsets_by_timestamp(topic, start.clone().into())
+    .await?;
+    let writer = ExportWriter::create(Path::new(&path), &format)?;
+    let now_epoch = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap_or_else(|_err| Duration::from_millis(0))
+    .as_millis();
+
+    let export_id = format!("export_{now_epoch}/{topic}/{start}");
+    let (sender, mut receiver) = mpsc::channel(1);
+    let mut session = ConsumerSession::new(export_id.clone(), SessionKind::Export, cluster.name, topic.to_owned(), start);
+    session.end = end;
+    app_state.active_consumers.start(session, sender, None)?;
+
+    let out_id = export_id.clone();
+    let exported_topic = topic.to_owned();
+    let start_offsets = offsets_map
+    .get(topic)
+    .map(|offsets| offsets.iter().copied().collect::<HashMap<i32, i64>>())
+    .unwrap_or_default();
+    tokio::spawn(async move {
+      sleep(Duration::from_secs(1)).await; // Let frontend subscribe to events.
+      let progress_event_name = format!("{export_id}/progress");
+      let sessions = &app_handle.state::<ApplicationState>().active_consumers;
+      let summary = export::export_range(stream, &exported_topic, start_offsets, end_offsets, writer, &mut receiver, |progress| {
+        sessions.update(&export_id, |session| session.consumed = progress.written);
+        let _ = app_handle.emit(&progress_event_name, progress);
+      }).await;
+
+      let reason = match (&summary.error, summary.stopped) {
+        (Some(_), _) => StopReason::Failed,
+        (None, true) => StopReason::Stopped,
+        (None, false) => StopReason::EndOffsets,
+      };
+      sessions.finish(&export_id, reason, summary.error.clone());
+      if let Err(err) = app_handle.emit(&format!("{export_id}/done"), summary) {
+        eprintln!("Export on `{}` finished, could not emit summary: {}", export_id, err);
+      }
+    });
+
+    Ok((out_id, offsets_map))
+  }
+
+  /// Hands `control` to the running consumer or export `consumer_id`.
+  async fn send_consumer_control(
+    state: &ApplicationState,
+    consumer_id: &str,
+    control: ConsumerControl,
+  ) -> Result<(), AppError> {
+    let sender = state
+    .active_consumers
+    .control(consumer_id)?
+    .ok_or_else(|| AppError::invalid(format!("Consumer '{}' has already ended", consumer_id)))?;
+    sender.send(control).await.map_err(|err| AppError::internal(err.to_string()))
+  }
+
+  /// Stopping a consumer that already ended does nothing, its session stays queryable either way.
   #[tauri::command]
   pub async fn stop_consumer(
     state: State<'_, ApplicationState>,
     consumer_id: String,
-  ) -> Result<(), String> {
-    let cancel = {
-      let map = state
-      .active_consumers
-      .lock()
-      .map_err(|err| err.to_string())?;
-      let cancel = map.get(&consumer_id).cloned().ok_or(format!(
-        "there is no such consumer running on channel: '{}'",
-        consumer_id
-      ))?;
-      cancel
-    };
-    
-    let _ = cancel.send(()).await.map_err(|err| err.to_string())?;
-    
-    state
-    .active_consumers
-    .lock()
-    .and_then(|mut map| Ok(map.remove(&consumer_id)))
-    .map_err(|err| err.to_string())?;
+  ) -> Result<(), AppError> {
+    if let Some(sender) = state.active_consumers.control(&consumer_id)? {
+      // A closed channel means the consumer ended meanwhile.
+      let _ = sender.send(ConsumerControl::Stop).await;
+    }
+    Ok(())
+  }
+
+  /// Stops fetching without ending the consumer, its position and counters are kept.
+  #[tauri::command]
+  pub async fn pause_consumer(state: State<'_, ApplicationState>, consumer_id: String) -> Result<(), AppError> {
+    send_consumer_control(&state, &consumer_id, ConsumerControl::Pause).await
+  }
+
+  #[tauri::command]
+  pub async fn resume_consumer(state: State<'_, ApplicationState>, consumer_id: String) -> Result<(), AppError> {
+    send_consumer_control(&state, &consumer_id, ConsumerControl::Resume).await
+  }
+
+  /// Moves partitions of a running consumer to new starts, returns the offsets they continue from.
+  #[tauri::command]
+  pub async fn seek_consumer(
+    state: State<'_, ApplicationState>,
+    consumer_id: String,
+    partitions: HashMap<i32, PartitionOffset>,
+  ) -> Result<HashMap<i32, i64>, AppError> {
+    let (reply, offsets) = oneshot::channel();
+    send_consumer_control(&state, &consumer_id, ConsumerControl::Seek(partitions, reply)).await?;
+    offsets
+    .await
+    .map_err(|_| AppError::invalid(format!("Consumer '{}' ended before seeking", consumer_id)))?
+  }
+
+  /// Replaces the filter of a running consumer, `None` lets every message through.
+  #[tauri::command]
+  pub async fn change_consumer_filter(
+    state: State<'_, ApplicationState>,
+    consumer_id: String,
+    filter: Option<MessageFilter>,
+  ) -> Result<(), AppError> {
+    let compiled = filter.as_ref().map(|filter| filter.compile()).transpose()?;
+    send_consumer_control(&state, &consumer_id, ConsumerControl::ChangeFilter(compiled)).await?;
+    state.active_consumers.update(&consumer_id, |session| session.filter = filter);
     Ok(())
   }
+
+  /// Acknowledges `count` more emitted messages of a consumer as shown, a paused consumer resumes
+  /// once enough are. Consumers that already ended are ignored, their last batches may arrive late.
+  #[tauri::command]
+  pub fn acknowledge_messages(
+    state: State<ApplicationState>,
+    consumer_id: String,
+    count: u64,
+  ) -> Result<(), AppError> {
+    state.active_consumers.acknowledge(&consumer_id, count)
+  }
   
+  /// Running consumers and exports, and the ones that ended recently.
   #[tauri::command]
-  pub fn get_all_active_consumers(app_state: State<ApplicationState>) -> Result<Vec<String>, String> {
-    let consumers: Vec<String> = app_state
-    .active_consumers
-    .lock()
-    .unwrap()
-    .keys()
-    .cloned()
-    .collect();
-    
-    Ok(consumers)
+  pub fn get_all_active_consumers(app_state: State<ApplicationState>) -> Result<Vec<ConsumerSession>, AppError> {
+    app_state.active_consumers.sessions()
+  }
+
+  #[tauri::command]
+  pub fn get_consumer_session(app_state: State<ApplicationState>, consumer_id: String) -> Result<ConsumerSession, AppError> {
+    app_state.active_consumers.session(&consumer_id)
   }
   
   #[tauri::command(async)]
@@ -267,18 +834,55 @@ pub fn get_group_offsets(
     topic: &str,
     start: FetchOffset,
     end: Option<FetchOffset>,
-  ) -> Result<(String, TopicOffsetsMap), String> {
-    let bootstrap_servers = app_state
+    partitions: Option<HashMap<i32, PartitionOffset>>,
+    display: Option<DisplayFormat>,
+    formats: Option<TopicFormats>,
+    filter: Option<MessageFilter>,
+    stop: Option<StopConditions>,
+    delivery: Option<DeliveryOptions>,
+    overrides: Option<HashMap<String, String>>,
+  ) -> Result<(String, TopicOffsetsMap), AppError> {
+    let display = display.unwrap_or_default();
+    let session_filter = filter.clone();
+    let mut filter = filter.map(|filter| filter.compile()).transpose()?;
+    let stop = stop.unwrap_or_default();
+    stop.validate()?;
+    let delivery = delivery.unwrap_or_default();
+    delivery.validate()?;
+    let base_cluster = app_state
     .config
     .lock()
     .unwrap()
-    .default_cluster_config()
-    .bootstrap_servers;
-    let mut stream = KafkaConsumer::connect(bootstrap_servers);
+    .default_cluster_config();
+    let cluster = base_cluster.with_overrides(overrides)?;
+    // Chosen formats are remembered for the topic, without a choice the remembered ones apply.
+    let formats = match formats {
+      Some(formats) => {
+        app_state
+        .config
+        .lock()?
+        .set_topic_formats(&cluster.name, topic, formats.to_owned())?;
+        formats
+      },
+      None => cluster.topic_formats.get(topic).cloned().unwrap_or_default(),
+    };
+    // The registry client is pooled per cluster, overrides only apply to the Kafka clients.
+    let registry = app_state.clients.schema_registry(&base_cluster, &app_state.secrets)?;
+    let deserializers = MessageDeserializers::new(&formats, registry)?;
+    let mut stream = match stop.end_of_partitions {
+      true => KafkaConsumer::connect_bounded(&cluster, &app_state.secrets)?,
+      false => KafkaConsumer::connect(&cluster, &app_state.secrets)?,
+    };
     
-    let offsets_map = stream
-    .assign_offsets_by_timestamp(topic, start.clone().into())
-    .await?;
+    // Only the listed partitions are read when given, each from its own offset.
+    let offsets_map = match &partitions {
+      Some(starts) => stream
+      .assign_partition_offsets(&cluster, &app_state.secrets, topic, starts)
+      .await?,
+      None => stream
+      .assign_offsets_by_timestamp(topic, start.clone().into())
+      .await?,
+    };
     let now_epoch = SystemTime::now()
     .duration_since(UNIX_EPOCH)
     .unwrap_or_else(|_err| Duration::from_millis(0))
@@ -286,27 +890,36 @@ pub fn get_group_offsets(
     
     let event_name = format!("consumer_{now_epoch}/{topic}/{start}");
     let (sender, mut receiver) = mpsc::channel(1);
+    let (ack_sender, mut acknowledged) = watch::channel(0);
     
-    app_state
-    .active_consumers
-    .lock()
-    .unwrap()
-    .insert(event_name.clone(), sender);
+    let mut session = ConsumerSession::new(
+      event_name.clone(),
+      SessionKind::Consumer,
+      cluster.name.to_owned(),
+      topic.to_owned(),
+      start,
+    );
+    session.end = end.clone();
+    session.partitions = partitions;
+    session.filter = session_filter;
+    app_state.active_consumers.start(session, sender, Some(ack_sender))?;
     
     let out_ev = event_name.clone();
     let consumed_topic = topic.to_owned();
     let mut partitions_current_offsets = offsets_map
     .get(topic)
-    .unwrap()
-    .iter()
-    .map(|(k, v)| (k.clone(), v.clone()))
-    .collect::<HashMap<i32, i64>>();
+    .map(|offsets| offsets.iter().map(|(k, v)| (k.clone(), v.clone())).collect::<HashMap<i32, i64>>())
+    .unwrap_or_default();
     
     println!("Spawning Thread to consume messages");
     tokio::spawn(async move {
       // TODO: we can subscribe to frontend event before hitting this command, and gen consumer id at frontend
       sleep(Duration::from_secs(1)).await; // Let frontend subscribe to events.
       let consumed_topic = consumed_topic.as_str();
+      let error_event_name = format!("{event_name}/error");
+      let progress_event_name = format!("{event_name}/progress");
+      let (mut scanned, mut matched, mut bytes) = (0, 0, 0);
+      let mut last_progress = Instant::now();
       let end_offsets = end
       .filter(|e| !matches!(e, FetchOffset::Beginning))
       .and_then(|end| {
@@ -321,18 +934,133 @@ pub fn get_group_offsets(
             Offset::Offset(o) => (partition.to_owned(), o.to_owned()),
             _ => (partition.to_owned(), 0)
           })
+          // Partitions left out of the assignment never reach their end.
+          .filter(|(partition, _)| partitions_current_offsets.contains_key(partition))
           .collect::<HashMap<i32, i64>>()
-        ).ok()
+        )
+        .inspect_err(|err| {
+          // Without end offsets the consumer keeps reading until it is stopped.
+          let _ = app_handle.emit(&error_event_name, ConsumerError { error: err.clone(), stopped: false });
+        })
+        .ok()
       });
+      let started = time::Instant::now();
+      let deadline = stop.max_duration().map(|duration| started + duration);
+      let mut last_message = started;
+      // Partitions that were read up to their high watermark, they stay done when more messages arrive.
+      let mut partitions_at_end = HashSet::new();
+      
+      // Messages are emitted in batches, the UI acknowledges each one it has shown.
+      let mut batch: Vec<MessageEnvelope<Decoded, Decoded>> = Vec::new();
+      let mut batch_started = started;
+      let (mut emitted, mut acked, mut dropped, mut read_bytes) = (0u64, 0, 0, 0);
+      // Fetching is paused while the UI falls behind or while it is held through `pause_consumer`.
+      let (mut backpressured, mut held, mut paused) = (false, false, false);
+      let (mut last_scanned, mut last_read_bytes) = (0, 0);
+      let sessions = &app_handle.state::<ApplicationState>().active_consumers;
+      // The error that ended the consumer, it was emitted on the error event as well.
+      let mut failure = None;
+      let mut ticker = time::interval(delivery.batch_window().min(SCAN_PROGRESS_INTERVAL));
+      ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+      let emit_batch = |batch: &mut Vec<MessageEnvelope<Decoded, Decoded>>| {
+        let count = batch.len() as u64;
+        app_handle.emit(&event_name, Some(std::mem::take(batch))).map(|_| count)
+      };
       
-      loop {
+      let reason = loop {
+        if paused != (backpressured || held) {
+          paused = backpressured || held;
+          let result = if paused { stream.pause() } else { stream.resume() };
+          if let Err(error) = result {
+            let _ = app_handle.emit(&error_event_name, ConsumerError { error, stopped: false });
+          }
+          last_message = time::Instant::now();
+          sessions.update(&event_name, |session| {
+            session.state = if paused { SessionState::Paused } else { SessionState::Running };
+          });
+        }
+        // A paused consumer is not idle.
+        let idle_deadline = stop.idle_timeout().filter(|_| !paused).map(|timeout| last_message + timeout);
         tokio::select! {
-          _ = receiver.recv() => {
-            app_handle.emit::<Option<MessageEnvelope<String, String>>>(&event_name, None).expect("Failed to emit event");
-            break;
+          control = receiver.recv() => match control {
+            None | Some(ConsumerControl::Stop) => break StopReason::Stopped,
+            Some(ConsumerControl::Pause) => held = true,
+            Some(ConsumerControl::Resume) => held = false,
+            Some(ConsumerControl::Seek(starts, reply)) => {
+              let secrets = &app_handle.state::<ApplicationState>().secrets;
+              let result = stream.seek_partition_offsets(&cluster, secrets, consumed_topic, &starts);
+              if let Ok(offsets) = &result {
+                partitions_current_offsets.extend(offsets);
+                partitions_at_end.retain(|partition| !offsets.contains_key(partition));
+              }
+              let _ = reply.send(result);
+            },
+            Some(ConsumerControl::ChangeFilter(changed)) => filter = changed,
+          },
+          _ = time::sleep_until(deadline.unwrap_or(started)), if deadline.is_some() => break StopReason::MaxDuration,
+          _ = time::sleep_until(idle_deadline.unwrap_or(started)), if idle_deadline.is_some() => break StopReason::IdleTimeout,
+          Ok(()) = acknowledged.changed() => {
+            acked = *acknowledged.borrow_and_update();
+            if backpressured && emitted.saturating_sub(acked) <= delivery.max_pending / 2 {
+              backpressured = false;
+            }
+          },
+          _ = ticker.tick() => {
+            if !batch.is_empty() && batch_started.elapsed() >= delivery.batch_window() {
+              match emit_batch(&mut batch) {
+                Ok(count) => emitted += count,
+                Err(err) => {
+                  eprintln!("Consumer on `{}` stopped, could not emit messages: {}", event_name, err);
+                  failure = Some(AppError::from(err));
+                  break StopReason::Failed;
+                },
+              }
+            }
+            if last_progress.elapsed() >= SCAN_PROGRESS_INTERVAL {
+              let seconds = last_progress.elapsed().as_secs_f64();
+              let _ = app_handle.emit(&progress_event_name, ScanProgress {
+                scanned,
+                matched,
+                dropped,
+                pending: emitted.saturating_sub(acked),
+                paused,
+                messages_per_second: (scanned - last_scanned) as f64 / seconds,
+                bytes_per_second: (read_bytes - last_read_bytes) as f64 / seconds,
+                offsets: partitions_current_offsets.clone(),
+                lag: stream.lag(consumed_topic, &partitions_current_offsets),
+              });
+              (last_scanned, last_read_bytes) = (scanned, read_bytes);
+              last_progress = Instant::now();
+              sessions.update(&event_name, |session| {
+                session.consumed = scanned;
+                session.offsets = partitions_current_offsets.clone();
+              });
+            }
           },
-          result = stream.get_next_message().fuse() => {
-            let message = result.expect("Could not get next message");
+          // Only the receive races the other branches, decoding below is never cancelled halfway.
+          result = stream.get_next_record() => {
+            let message = match result {
+              Ok(ConsumedRecord::Message(message)) => KafkaConsumer::decode_message(&message, display, &deserializers).await,
+              Ok(ConsumedRecord::PartitionEof(partition)) => {
+                partitions_at_end.insert(partition);
+                if partitions_current_offsets.keys().all(|partition| partitions_at_end.contains(partition)) {
+                  break StopReason::EndOfPartitions;
+                }
+                continue;
+              }
+              Err(error) => {
+                // Transient errors are reported and consuming continues, librdkafka recovers on its own.
+                let stopped = !error.is_retryable();
+                eprintln!("Consumer on `{}` failed: {}", event_name, error);
+                let _ = app_handle.emit(&error_event_name, ConsumerError { error: error.clone(), stopped });
+                if stopped {
+                  failure = Some(error);
+                  break StopReason::Failed;
+                }
+                continue;
+              }
+            };
+            last_message = time::Instant::now();
             let (consumed_partition, consumed_offset) = (message.partition, message.offset);
             
             // Optionally, check if the received message is beyond the end offset, dont emit
@@ -340,8 +1068,32 @@ pub fn get_group_offsets(
               return &consumed_offset >= end.get(&consumed_partition).unwrap_or_else(|| &0);
             });
             if !is_message_beyond_end_offset {
-              // println!("Emitted message on channel `{}`: {:?}", event_name, message);
-              app_handle.emit(&event_name, Some(message)).expect("Failed to emit event");
+              scanned += 1;
+              read_bytes += message.size();
+              // Filtered out messages never reach the webview.
+              if filter.as_ref().is_none_or(|filter| filter.matches(&message)) {
+                matched += 1;
+                let pending = (emitted + batch.len() as u64).saturating_sub(acked);
+                if delivery.drop_when_full && pending >= delivery.max_pending {
+                  dropped += 1;
+                } else {
+                  bytes += message.size();
+                  if batch.is_empty() {
+                    batch_started = time::Instant::now();
+                  }
+                  batch.push(message);
+                }
+                if batch.len() >= delivery.batch_size {
+                  match emit_batch(&mut batch) {
+                    Ok(count) => emitted += count,
+                    Err(err) => {
+                      eprintln!("Consumer on `{}` stopped, could not emit messages: {}", event_name, err);
+                      failure = Some(AppError::from(err));
+                      break StopReason::Failed;
+                    },
+                  }
+                }
+              }
             } else {
               println!("Message on `{}` at `{}` ignored due to beyond end offset!", consumed_partition, consumed_offset);
             }
@@ -349,6 +1101,10 @@ pub fn get_group_offsets(
             // Update current offsets.
             partitions_current_offsets.entry(consumed_partition).and_modify(move |offset| *offset=consumed_offset+1);
 
+            if !delivery.drop_when_full && emitted.saturating_sub(acked) >= delivery.max_pending {
+              backpressured = true;
+            }
+
             // Optionally, Check if all current offsets have reached the end.
             let all_partitions_ended = end_offsets.as_ref().is_some_and(|offsets| {
               return offsets.iter().all(
@@ -356,13 +1112,48 @@ pub fn get_group_offsets(
               );
             });
             if all_partitions_ended {
-              app_handle.emit::<Option<MessageEnvelope<String, String>>>(&event_name, None).expect("Failed to emit event");
-              println!("Consumer on `{}` Ended!", event_name);
-              break;
+              break StopReason::EndOffsets;
+            }
+            if stop.max_messages.is_some_and(|max| matched >= max) {
+              break StopReason::MaxMessages;
+            }
+            if stop.max_bytes.is_some_and(|max| bytes >= max) {
+              break StopReason::MaxBytes;
             }
           }
         }
+      };
+
+      if !batch.is_empty() {
+        emitted += emit_batch(&mut batch).unwrap_or_default();
       }
+      let seconds = last_progress.elapsed().as_secs_f64();
+      let _ = app_handle.emit(&progress_event_name, ScanProgress {
+        scanned,
+        matched,
+        dropped,
+        pending: emitted.saturating_sub(acked),
+        paused,
+        messages_per_second: (scanned - last_scanned) as f64 / seconds,
+        bytes_per_second: (read_bytes - last_read_bytes) as f64 / seconds,
+        offsets: partitions_current_offsets.clone(),
+        lag: stream.lag(consumed_topic, &partitions_current_offsets),
+      });
+      sessions.update(&event_name, |session| {
+        session.consumed = scanned;
+        session.offsets = partitions_current_offsets.clone();
+      });
+      sessions.finish(&event_name, reason, failure);
+      let _ = app_handle.emit(&format!("{event_name}/done"), ConsumerSummary {
+        reason,
+        scanned,
+        matched,
+        bytes,
+        dropped,
+        offsets: partitions_current_offsets,
+        elapsed_ms: started.elapsed().as_millis(),
+      });
+      let _ = app_handle.emit::<Option<Vec<MessageEnvelope<Decoded, Decoded>>>>(&event_name, None);
     });
     
     Ok((out_ev, offsets_map))
@@ -1,68 +1,301 @@
-use std::{collections::HashMap, sync::Mutex};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
 
+use rdkafka::ClientConfig;
 use serde::{Deserialize, Serialize};
 
+use crate::core::{error::AppError, secrets::SecretStore};
+use crate::kafka::{
+    deserializer::TopicFormats, metadata::MetadataCache, pool::ClientPool, properties::validate_client_overrides,
+    schema_registry::SchemaRegistryConfig, security::SecurityConfig, session::ConsumerRegistry,
+};
+
+const CONFIG_FILE_NAME: &str = "config.json";
+const DEFAULT_CLUSTER_NAME: &str = "local";
+const DEFAULT_METADATA_TTL_SECS: u64 = 30;
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ClusterConfig {
     pub name: String,
     pub bootstrap_servers: Vec<String>,
+    #[serde(default)]
+    pub security: SecurityConfig,
+    /// Extra librdkafka properties set on every client created for this cluster.
+    #[serde(default)]
+    pub client_overrides: HashMap<String, String>,
+    /// Key and value formats last used to consume each topic.
+    #[serde(default)]
+    pub topic_formats: HashMap<String, TopicFormats>,
+    /// Registry the `SchemaRegistry` key and value format looks schemas up in.
+    #[serde(default)]
+    pub schema_registry: Option<SchemaRegistryConfig>,
+}
+impl ClusterConfig {
+    /// Base client config every client for this cluster is built from, secrets are only
+    /// resolved here so they never outlive the client config.
+    pub fn client_config(&self, secrets: &SecretStore) -> Result<ClientConfig, AppError> {
+        let mut config = ClientConfig::new();
+        config.set("bootstrap.servers", self.bootstrap_servers.join(","));
+        self.security
+            .apply(&mut config, secrets)
+            .map_err(|err| err.context(format!("Cluster '{}'", self.name)))?;
+        config.extend(self.client_overrides.to_owned());
+        Ok(config)
+    }
+
+    /// Returns a copy of this cluster with per-operation `overrides` layered on top of its own.
+    pub fn with_overrides(&self, overrides: Option<HashMap<String, String>>) -> Result<Self, AppError> {
+        let Some(overrides) = overrides.filter(|o| !o.is_empty()) else {
+            return Ok(self.to_owned());
+        };
+        validate_client_overrides(&overrides)?;
+
+        let mut cluster = self.to_owned();
+        cluster.client_overrides.extend(overrides);
+        Ok(cluster)
+    }
+
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.name.trim().is_empty() {
+            return Err(AppError::invalid("Cluster name can not be empty"));
+        }
+        if self.bootstrap_servers.is_empty()
+            || self.bootstrap_servers.iter().any(|server| server.trim().is_empty())
+        {
+            return Err(AppError::invalid(format!(
+                "Cluster '{}' needs at least one bootstrap server and none of them can be empty",
+                self.name
+            )));
+        }
+        self.security
+            .validate()
+            .and_then(|_| validate_client_overrides(&self.client_overrides))
+            .and_then(|_| self.schema_registry.as_ref().map_or(Ok(()), SchemaRegistryConfig::validate))
+            .map_err(|err| err.context(format!("Cluster '{}'", self.name)))
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     clusters: HashMap<String, ClusterConfig>,
     default_cluster: String,
+    /// How long fetched cluster metadata is served from the cache.
+    #[serde(default = "default_metadata_ttl_secs")]
+    metadata_ttl_secs: u64,
+    /// Location the config is persisted to, `None` keeps it in memory only.
+    #[serde(skip)]
+    path: Option<PathBuf>,
 }
-impl Config {
-    pub fn clusters(&self) -> Vec<ClusterConfig> {
-        self.clusters.to_owned().into_values().collect()
-    }
-    pub fn load() -> Self {
-        let clusters = HashMap::from([
-            (
-                "dev".to_owned(),
-                ClusterConfig {
-                    name: "dev".to_owned(),
-                    bootstrap_servers: vec!["192.168.225.10:9092".to_owned()],
-                },
-            ),
-            (
-                "prod".to_owned(),
-                ClusterConfig {
-                    name: "prod".to_owned(),
-                    bootstrap_servers: vec!["10.10.18.89:9093".to_owned()],
-                },
-            ),
-            (
-                "local".to_owned(),
-                ClusterConfig {
-                    name: "local".to_owned(),
-                    bootstrap_servers: vec!["localhost:9092".to_owned()],
-                },
-            ),
-        ]);
+impl Default for Config {
+    fn default() -> Self {
+        let clusters = HashMap::from([(
+            DEFAULT_CLUSTER_NAME.to_owned(),
+            ClusterConfig {
+                name: DEFAULT_CLUSTER_NAME.to_owned(),
+                bootstrap_servers: vec!["localhost:9092".to_owned()],
+                security: SecurityConfig::default(),
+                client_overrides: HashMap::new(),
+                topic_formats: HashMap::new(),
+                schema_registry: None,
+            },
+        )]);
 
         Self {
             clusters,
-            default_cluster: "local".to_owned(),
+            default_cluster: DEFAULT_CLUSTER_NAME.to_owned(),
+            metadata_ttl_secs: DEFAULT_METADATA_TTL_SECS,
+            path: None,
+        }
+    }
+}
+fn default_metadata_ttl_secs() -> u64 {
+    DEFAULT_METADATA_TTL_SECS
+}
+impl Config {
+    pub fn metadata_ttl(&self) -> Duration {
+        Duration::from_secs(self.metadata_ttl_secs)
+    }
+
+    pub fn set_metadata_ttl(&mut self, seconds: u64) -> Result<(), AppError> {
+        self.metadata_ttl_secs = seconds;
+        self.save()
+    }
+
+    pub fn clusters(&self) -> Vec<ClusterConfig> {
+        let mut clusters: Vec<ClusterConfig> = self.clusters.to_owned().into_values().collect();
+        clusters.sort_by(|a, b| a.name.cmp(&b.name));
+        clusters
+    }
+
+    /// Loads the config from `config_dir`, a missing file is created with the default clusters.
+    pub fn load(config_dir: &Path) -> Result<Self, AppError> {
+        let path = config_dir.join(CONFIG_FILE_NAME);
+        if !path.exists() {
+            let config = Self {
+                path: Some(path),
+                ..Self::default()
+            };
+            config.save()?;
+            return Ok(config);
         }
+
+        let content = fs::read_to_string(&path).map_err(|err| {
+            AppError::Io(format!("Could not read config file '{}': {}", path.display(), err))
+        })?;
+        let mut config: Config = serde_json::from_str(&content).map_err(|err| {
+            AppError::Io(format!("Config file '{}' is corrupt: {}", path.display(), err))
+        })?;
+        config.path = Some(path);
+
+        Ok(config)
     }
 
-    pub fn set_default_cluster(&mut self, cluster: &str) -> Result<ClusterConfig, String> {
-        if !self.clusters.contains_key(cluster) {
-            return Err(format!(
-                "Cluster key '{cluster}' does not exist in the config"
-            ));
+    /// Moves an unreadable config file aside and starts over with the default clusters,
+    /// so the broken file is kept for the user instead of being overwritten on next save.
+    pub fn recover(config_dir: &Path) -> Self {
+        let path = config_dir.join(CONFIG_FILE_NAME);
+        let backup = path.with_extension("json.bak");
+        if path.exists() {
+            if let Err(err) = fs::rename(&path, &backup) {
+                eprintln!("Could not back up config file to '{}': {}", backup.display(), err);
+            }
         }
 
+        let config = Self {
+            path: Some(path),
+            ..Self::default()
+        };
+        if let Err(err) = config.save() {
+            eprintln!("{}", err);
+        }
+        config
+    }
+
+    pub fn save(&self) -> Result<(), AppError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| {
+                AppError::Io(format!("Could not create config directory '{}': {}", dir.display(), err))
+            })?;
+        }
+        let content = serde_json::to_string_pretty(self).map_err(|err| AppError::internal(err.to_string()))?;
+
+        // Write to a temporary file first so a crash never leaves a half written config behind.
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, content)
+            .and_then(|_| fs::rename(&tmp_path, path))
+            .map_err(|err| AppError::Io(format!("Could not write config file '{}': {}", path.display(), err)))
+    }
+
+    pub fn add_cluster(&mut self, cluster: ClusterConfig) -> Result<ClusterConfig, AppError> {
+        cluster.validate()?;
+        if self.clusters.contains_key(&cluster.name) {
+            return Err(AppError::invalid(format!("Cluster '{}' already exists", cluster.name)));
+        }
+
+        self.clusters.insert(cluster.name.to_owned(), cluster.to_owned());
+        self.save()?;
+
+        Ok(cluster)
+    }
+
+    pub fn update_cluster(&mut self, cluster: ClusterConfig) -> Result<ClusterConfig, AppError> {
+        cluster.validate()?;
+        if !self.clusters.contains_key(&cluster.name) {
+            return Err(AppError::invalid(format!(
+                "Cluster key '{}' does not exist in the config",
+                cluster.name
+            )));
+        }
+
+        self.clusters.insert(cluster.name.to_owned(), cluster.to_owned());
+        self.save()?;
+
+        Ok(cluster)
+    }
+
+    pub fn rename_cluster(&mut self, cluster: &str, new_name: &str) -> Result<ClusterConfig, AppError> {
+        if self.clusters.contains_key(new_name) {
+            return Err(AppError::invalid(format!("Cluster '{new_name}' already exists")));
+        }
+        let mut renamed = self
+            .clusters
+            .get(cluster)
+            .cloned()
+            .ok_or_else(|| AppError::invalid(format!("Cluster key '{cluster}' does not exist in the config")))?;
+        renamed.name = new_name.to_owned();
+        renamed.validate()?;
+
+        self.clusters.remove(cluster);
+        self.clusters.insert(new_name.to_owned(), renamed.to_owned());
+        if self.default_cluster == cluster {
+            self.default_cluster = new_name.to_owned();
+        }
+        self.save()?;
+
+        Ok(renamed)
+    }
+
+    pub fn remove_cluster(&mut self, cluster: &str) -> Result<ClusterConfig, AppError> {
+        let removed = self
+            .clusters
+            .remove(cluster)
+            .ok_or_else(|| AppError::invalid(format!("Cluster key '{cluster}' does not exist in the config")))?;
+
+        if self.default_cluster == cluster {
+            self.default_cluster = self
+                .clusters()
+                .first()
+                .map(|c| c.name.to_owned())
+                .unwrap_or_default();
+        }
+        self.save()?;
+
+        Ok(removed)
+    }
+
+    /// Remembers the formats of `topic`, formats without key and value are forgotten.
+    pub fn set_topic_formats(&mut self, cluster: &str, topic: &str, formats: TopicFormats) -> Result<(), AppError> {
+        let cluster_config = self
+            .clusters
+            .get_mut(cluster)
+            .ok_or_else(|| AppError::invalid(format!("Cluster key '{cluster}' does not exist in the config")))?;
+        if formats == TopicFormats::default() {
+            cluster_config.topic_formats.remove(topic);
+        } else {
+            cluster_config.topic_formats.insert(topic.to_owned(), formats);
+        }
+        self.save()
+    }
+
+    pub fn set_default_cluster(&mut self, cluster: &str) -> Result<ClusterConfig, AppError> {
+        let cluster_config = self
+            .clusters
+            .get(cluster)
+            .cloned()
+            .ok_or_else(|| AppError::invalid(format!("Cluster key '{cluster}' does not exist in the config")))?;
+
         self.default_cluster = cluster.to_owned();
+        self.save()?;
 
-        Ok(self.clusters.get(cluster).unwrap().to_owned())
+        Ok(cluster_config)
     }
     pub fn default_cluster_config(&self) -> ClusterConfig {
         let default_cluster = ClusterConfig {
             name: "default".to_owned(),
             bootstrap_servers: vec!["localhost:9092".to_owned()],
+            security: SecurityConfig::default(),
+            client_overrides: HashMap::new(),
+            topic_formats: HashMap::new(),
+            schema_registry: None,
         };
 
         let cluster_config = self
@@ -76,14 +309,39 @@ impl Config {
 
 pub struct ApplicationState {
     pub config: Mutex<Config>,
-    pub active_consumers: Mutex<HashMap<String, tokio::sync::mpsc::Sender<()>>>
+    pub secrets: SecretStore,
+    pub clients: ClientPool,
+    pub active_consumers: ConsumerRegistry,
+    pub metadata: MetadataCache,
 }
 
 impl ApplicationState {
-    pub fn load() -> Self {
+    /// Stops every consumer reading from `cluster` and drops its cached metadata.
+    pub fn reset_cluster(&self, cluster: &str) -> Result<(), AppError> {
+        self.active_consumers.stop_cluster(cluster)?;
+        self.metadata.remove(cluster);
+        Ok(())
+    }
+}
+
+impl ApplicationState {
+    pub fn load(config_dir: &Path) -> Self {
+        let config = Config::load(config_dir).unwrap_or_else(|err| {
+            eprintln!("{}, falling back to default configuration", err);
+            Config::recover(config_dir)
+        });
+
+        let secrets = SecretStore::load(config_dir).unwrap_or_else(|err| {
+            eprintln!("{}, secrets will not be persisted", err);
+            SecretStore::unavailable(err)
+        });
+
         ApplicationState {
-            config: Mutex::new(Config::load()),
-            active_consumers: Mutex::new(HashMap::new())
+            config: Mutex::new(config),
+            secrets,
+            clients: ClientPool::default(),
+            active_consumers: ConsumerRegistry::default(),
+            metadata: MetadataCache::default(),
         }
     }
 }
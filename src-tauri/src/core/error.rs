@@ -0,0 +1,193 @@
+use std::{fmt::Display, sync::PoisonError};
+
+use rdkafka::{error::KafkaError, types::RDKafkaErrorCode};
+use serde::{Deserialize, Serialize, Serializer};
+
+/// The Kafka resource an error is about, so the UI can point at it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "content")]
+pub enum Resource {
+    Cluster(String),
+    Topic(String),
+    Partition { topic: String, partition: i32 },
+    Group(String),
+}
+
+/// Error returned by every command, serialized as `{ type, content }` for the frontend.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "content")]
+pub enum AppError {
+    /// Reported by librdkafka or a broker. `code` is missing for client side failures
+    /// without one, e.g. an invalid client configuration.
+    #[serde(rename_all = "camelCase")]
+    Kafka {
+        #[serde(serialize_with = "serialize_code")]
+        code: Option<RDKafkaErrorCode>,
+        message: String,
+        resource: Option<Resource>,
+        retryable: bool,
+    },
+    /// Reported by the schema registry, or reaching it failed. `status` is missing when no
+    /// response was received, `code` is the registry's own error code, e.g. `40403`.
+    #[serde(rename_all = "camelCase")]
+    SchemaRegistry {
+        status: Option<u16>,
+        code: Option<i64>,
+        message: String,
+    },
+    /// The request or a cluster setting is invalid, retrying without changes fails again.
+    Invalid(String),
+    /// A referenced secret is needed while the secret store is locked.
+    SecretsLocked,
+    /// Reading or writing a local file failed.
+    Io(String),
+    Internal(String),
+}
+
+fn serialize_code<S: Serializer>(code: &Option<RDKafkaErrorCode>, serializer: S) -> Result<S::Ok, S::Error> {
+    match code {
+        Some(code) => serializer.serialize_some(&format!("{:?}", code)),
+        None => serializer.serialize_none(),
+    }
+}
+
+impl AppError {
+    pub fn invalid(message: impl Into<String>) -> Self {
+        Self::Invalid(message.into())
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::Internal(message.into())
+    }
+
+    /// An error for a per-resource result code of an admin operation.
+    pub fn from_code(code: RDKafkaErrorCode, message: impl Into<String>) -> Self {
+        let message = message.into();
+        Self::Kafka {
+            code: Some(code),
+            message: if message.is_empty() { code.to_string() } else { message },
+            resource: None,
+            retryable: is_retryable(code),
+        }
+    }
+
+    /// Prefixes the message with `context`, e.g. the cluster a setting belongs to.
+    pub fn context(self, context: impl Display) -> Self {
+        match self {
+            Self::Kafka { code, message, resource, retryable } => Self::Kafka {
+                code,
+                message: format!("{}: {}", context, message),
+                resource,
+                retryable,
+            },
+            Self::SchemaRegistry { status, code, message } => Self::SchemaRegistry {
+                status,
+                code,
+                message: format!("{}: {}", context, message),
+            },
+            Self::Invalid(message) => Self::Invalid(format!("{}: {}", context, message)),
+            Self::Io(message) => Self::Io(format!("{}: {}", context, message)),
+            Self::Internal(message) => Self::Internal(format!("{}: {}", context, message)),
+            Self::SecretsLocked => Self::SecretsLocked,
+        }
+    }
+
+    /// Attaches the affected resource, only Kafka errors carry one.
+    pub fn on(mut self, affected: Resource) -> Self {
+        if let Self::Kafka { resource, .. } = &mut self {
+            *resource = Some(affected);
+        }
+        self
+    }
+
+    pub fn code(&self) -> Option<RDKafkaErrorCode> {
+        match self {
+            Self::Kafka { code, .. } => *code,
+            _ => None,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Kafka { retryable, .. } => *retryable,
+            Self::SchemaRegistry { status, .. } => status.is_none_or(|status| status >= 500),
+            _ => false,
+        }
+    }
+}
+
+impl Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Kafka { message, .. } | Self::SchemaRegistry { message, .. } => write!(f, "{}", message),
+            Self::Invalid(message) | Self::Io(message) | Self::Internal(message) => write!(f, "{}", message),
+            Self::SecretsLocked => write!(f, "Secret store is locked, unlock it with the master passphrase first"),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<KafkaError> for AppError {
+    fn from(err: KafkaError) -> Self {
+        let code = err.rdkafka_error_code();
+        Self::Kafka {
+            code,
+            message: err.to_string(),
+            resource: None,
+            retryable: code.is_some_and(is_retryable),
+        }
+    }
+}
+
+impl From<tauri::Error> for AppError {
+    fn from(err: tauri::Error) -> Self {
+        Self::Internal(err.to_string())
+    }
+}
+
+impl<T> From<PoisonError<T>> for AppError {
+    fn from(err: PoisonError<T>) -> Self {
+        Self::Internal(err.to_string())
+    }
+}
+
+/// Transient conditions that usually resolve on their own, e.g. timeouts or leader elections.
+fn is_retryable(code: RDKafkaErrorCode) -> bool {
+    matches!(
+        code,
+        RDKafkaErrorCode::BrokerTransportFailure
+            | RDKafkaErrorCode::Resolve
+            | RDKafkaErrorCode::MessageTimedOut
+            | RDKafkaErrorCode::AllBrokersDown
+            | RDKafkaErrorCode::OperationTimedOut
+            | RDKafkaErrorCode::QueueFull
+            | RDKafkaErrorCode::WaitingForCoordinator
+            | RDKafkaErrorCode::TimedOutQueue
+            | RDKafkaErrorCode::WaitCache
+            | RDKafkaErrorCode::Retry
+            | RDKafkaErrorCode::LeaderNotAvailable
+            | RDKafkaErrorCode::NotLeaderForPartition
+            | RDKafkaErrorCode::RequestTimedOut
+            | RDKafkaErrorCode::BrokerNotAvailable
+            | RDKafkaErrorCode::ReplicaNotAvailable
+            | RDKafkaErrorCode::NetworkException
+            | RDKafkaErrorCode::CoordinatorLoadInProgress
+            | RDKafkaErrorCode::CoordinatorNotAvailable
+            | RDKafkaErrorCode::NotCoordinator
+            | RDKafkaErrorCode::NotEnoughReplicas
+            | RDKafkaErrorCode::NotEnoughReplicasAfterAppend
+            | RDKafkaErrorCode::RebalanceInProgress
+            | RDKafkaErrorCode::NotController
+            | RDKafkaErrorCode::ConcurrentTransactions
+            | RDKafkaErrorCode::KafkaStorageError
+            | RDKafkaErrorCode::FetchSessionIdNotFound
+            | RDKafkaErrorCode::InvalidFetchSessionEpoch
+            | RDKafkaErrorCode::UnknownLeaderEpoch
+            | RDKafkaErrorCode::OffsetNotAvailable
+            | RDKafkaErrorCode::PreferredLeaderNotAvailable
+            | RDKafkaErrorCode::EligibleLeadersNotAvailable
+            | RDKafkaErrorCode::UnstableOffsetCommit
+            | RDKafkaErrorCode::ThrottlingQuotaExceeded
+    )
+}
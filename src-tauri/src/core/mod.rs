@@ -1,2 +1,4 @@
 pub mod commands;
 pub mod config;
+pub mod error;
+pub mod secrets;
@@ -0,0 +1,371 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::core::error::AppError;
+
+const SECRETS_FILE_NAME: &str = "secrets.json";
+/// Known plaintext encrypted with the master key, used to verify a passphrase on unlock.
+const CHECK_VALUE: &[u8] = b"kafka-client-secrets";
+
+/// Reference to a value kept in the [`SecretStore`], only the id is ever persisted in the config.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SecretRef(pub String);
+
+#[derive(Clone, Serialize, Deserialize)]
+struct EncryptedValue {
+    nonce: String,
+    data: String,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct SecretsFile {
+    salt: Option<String>,
+    check: Option<EncryptedValue>,
+    secrets: HashMap<String, EncryptedValue>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretStoreStatus {
+    pub initialized: bool,
+    pub unlocked: bool,
+    /// Why the secrets file could not be loaded, the store then starts empty and is not persisted.
+    pub load_error: Option<String>,
+}
+
+/// Credentials encrypted at rest with a key derived from a master passphrase.
+pub struct SecretStore {
+    path: Option<PathBuf>,
+    file: RwLock<SecretsFile>,
+    key: RwLock<Option<Key>>,
+    load_error: Option<String>,
+}
+
+impl SecretStore {
+    pub fn load(config_dir: &Path) -> Result<Self, AppError> {
+        let path = config_dir.join(SECRETS_FILE_NAME);
+        let file = if path.exists() {
+            let content = fs::read_to_string(&path).map_err(|err| {
+                AppError::Io(format!("Could not read secrets file '{}': {}", path.display(), err))
+            })?;
+            serde_json::from_str(&content).map_err(|err| {
+                AppError::Io(format!("Secrets file '{}' is corrupt: {}", path.display(), err))
+            })?
+        } else {
+            SecretsFile::default()
+        };
+
+        Ok(Self {
+            path: Some(path),
+            file: RwLock::new(file),
+            key: RwLock::new(None),
+            load_error: None,
+        })
+    }
+
+    /// An empty, in memory only store.
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            file: RwLock::new(SecretsFile::default()),
+            key: RwLock::new(None),
+            load_error: None,
+        }
+    }
+
+    /// An empty, in memory only store standing in for one that failed to load. The failure is reported
+    /// in the status and by every secret that can not be resolved.
+    pub fn unavailable(error: AppError) -> Self {
+        Self {
+            load_error: Some(error.to_string()),
+            ..Self::in_memory()
+        }
+    }
+
+    pub fn status(&self) -> Result<SecretStoreStatus, AppError> {
+        Ok(SecretStoreStatus {
+            initialized: self.file.read()?.salt.is_some(),
+            unlocked: self.key.read()?.is_some(),
+            load_error: self.load_error.to_owned(),
+        })
+    }
+
+    /// Unlocks the store, the first unlock sets the master passphrase.
+    pub fn unlock(&self, passphrase: &str) -> Result<(), AppError> {
+        if passphrase.is_empty() {
+            return Err(AppError::invalid("Master passphrase can not be empty"));
+        }
+
+        let mut file = self.file.write()?;
+        let key = match (&file.salt, &file.check) {
+            (Some(salt), Some(check)) => {
+                let key = derive_key(passphrase, &decode(salt)?)?;
+                decrypt(&key, check).map_err(|_| AppError::invalid("Invalid master passphrase"))?;
+                key
+            }
+            _ => {
+                let mut salt = [0u8; 16];
+                OsRng.fill_bytes(&mut salt);
+                let key = derive_key(passphrase, &salt)?;
+                file.salt = Some(BASE64.encode(salt));
+                file.check = Some(encrypt(&key, CHECK_VALUE)?);
+                self.save(&file)?;
+                key
+            }
+        };
+
+        *self.key.write()? = Some(key);
+        Ok(())
+    }
+
+    pub fn lock(&self) -> Result<(), AppError> {
+        *self.key.write()? = None;
+        Ok(())
+    }
+
+    /// Re-encrypts every secret with a key derived from `new_passphrase`.
+    pub fn change_passphrase(&self, passphrase: &str, new_passphrase: &str) -> Result<(), AppError> {
+        if new_passphrase.is_empty() {
+            return Err(AppError::invalid("Master passphrase can not be empty"));
+        }
+        self.unlock(passphrase)?;
+
+        let mut file = self.file.write()?;
+        let mut key_guard = self.key.write()?;
+        let key = key_guard.ok_or(AppError::SecretsLocked)?;
+
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let new_key = derive_key(new_passphrase, &salt)?;
+        let mut secrets = HashMap::with_capacity(file.secrets.len());
+        for (id, value) in file.secrets.iter() {
+            secrets.insert(id.to_owned(), encrypt(&new_key, &decrypt(&key, value)?)?);
+        }
+
+        let updated = SecretsFile {
+            salt: Some(BASE64.encode(salt)),
+            check: Some(encrypt(&new_key, CHECK_VALUE)?),
+            secrets,
+        };
+        self.save(&updated)?;
+        *file = updated;
+        *key_guard = Some(new_key);
+        Ok(())
+    }
+
+    /// Stores `value` under `id`, or under a new id when none is given, and returns the reference.
+    pub fn put(&self, id: Option<SecretRef>, value: &str) -> Result<SecretRef, AppError> {
+        let key = self.key()?;
+        let id = id.unwrap_or_else(new_secret_id);
+
+        let mut file = self.file.write()?;
+        file.secrets.insert(id.0.to_owned(), encrypt(&key, value.as_bytes())?);
+        self.save(&file)?;
+        Ok(id)
+    }
+
+    pub fn remove(&self, id: &SecretRef) -> Result<(), AppError> {
+        let mut file = self.file.write()?;
+        if file.secrets.remove(&id.0).is_some() {
+            self.save(&file)?;
+        }
+        Ok(())
+    }
+
+    pub fn resolve(&self, id: &SecretRef) -> Result<String, AppError> {
+        let key = self.key()?;
+        let file = self.file.read()?;
+        let value = file
+            .secrets
+            .get(&id.0)
+            .ok_or_else(|| match &self.load_error {
+                Some(error) => AppError::Io(format!(
+                    "Secret '{}' is unavailable, the secrets file could not be loaded: {}",
+                    id.0, error
+                )),
+                None => AppError::invalid(format!("Secret '{}' does not exist in the secret store", id.0)),
+            })?;
+
+        String::from_utf8(decrypt(&key, value)?).map_err(|err| AppError::internal(err.to_string()))
+    }
+
+    fn key(&self) -> Result<Key, AppError> {
+        self.key.read()?.ok_or(AppError::SecretsLocked)
+    }
+
+    fn save(&self, file: &SecretsFile) -> Result<(), AppError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| {
+                AppError::Io(format!("Could not create config directory '{}': {}", dir.display(), err))
+            })?;
+        }
+        let content = serde_json::to_string_pretty(file).map_err(|err| AppError::internal(err.to_string()))?;
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, content)
+            .and_then(|_| fs::rename(&tmp_path, path))
+            .map_err(|err| AppError::Io(format!("Could not write secrets file '{}': {}", path.display(), err)))
+    }
+}
+
+fn new_secret_id() -> SecretRef {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    SecretRef(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key, AppError> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| AppError::internal(format!("Could not derive key from passphrase: {}", err)))?;
+    Ok(key)
+}
+
+fn encrypt(key: &Key, plaintext: &[u8]) -> Result<EncryptedValue, AppError> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let data = ChaCha20Poly1305::new(key)
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| AppError::internal("Could not encrypt secret"))?;
+
+    Ok(EncryptedValue {
+        nonce: BASE64.encode(nonce),
+        data: BASE64.encode(data),
+    })
+}
+
+fn decrypt(key: &Key, value: &EncryptedValue) -> Result<Vec<u8>, AppError> {
+    let nonce = decode(&value.nonce)?;
+    if nonce.len() != 12 {
+        return Err(AppError::internal("Secret has an invalid nonce"));
+    }
+    ChaCha20Poly1305::new(key)
+        .decrypt(Nonce::from_slice(&nonce), decode(&value.data)?.as_slice())
+        .map_err(|_| AppError::internal("Could not decrypt secret"))
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, AppError> {
+    BASE64.decode(value).map_err(|err| AppError::internal(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{decode, SecretRef, SecretStore};
+    use crate::core::error::AppError;
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+    fn unlocked(passphrase: &str) -> SecretStore {
+        let secrets = SecretStore::in_memory();
+        secrets.unlock(passphrase).unwrap();
+        secrets
+    }
+
+    #[test]
+    fn secrets_open_with_the_passphrase_they_were_sealed_with() {
+        let secrets = unlocked("first");
+        let id = secrets.put(None, "s3cr3t").unwrap();
+        let named = secrets.put(Some(SecretRef("named".to_owned())), "other").unwrap();
+        assert_eq!(secrets.resolve(&id).unwrap(), "s3cr3t");
+        assert_eq!(secrets.resolve(&named).unwrap(), "other");
+
+        // Sealed values never hold the plaintext.
+        let file = secrets.file.read().unwrap();
+        assert!(file.secrets.values().all(|value| !decode(&value.data).unwrap().starts_with(b"s3cr3t")));
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let secrets = unlocked("first");
+        let id = secrets.put(None, "s3cr3t").unwrap();
+        secrets.lock().unwrap();
+
+        assert!(matches!(secrets.unlock("second"), Err(AppError::Invalid(_))));
+        assert!(!secrets.status().unwrap().unlocked);
+        assert!(matches!(secrets.unlock(""), Err(AppError::Invalid(_))));
+        secrets.unlock("first").unwrap();
+        assert_eq!(secrets.resolve(&id).unwrap(), "s3cr3t");
+    }
+
+    #[test]
+    fn locked_store_does_not_resolve() {
+        let secrets = unlocked("first");
+        let id = secrets.put(None, "s3cr3t").unwrap();
+        secrets.lock().unwrap();
+        assert!(matches!(secrets.resolve(&id), Err(AppError::SecretsLocked)));
+        assert!(matches!(secrets.put(None, "more"), Err(AppError::SecretsLocked)));
+    }
+
+    #[test]
+    fn changing_the_passphrase_reencrypts_every_secret() {
+        let secrets = unlocked("first");
+        let ids: Vec<SecretRef> =
+            ["one", "two", "three"].iter().map(|value| secrets.put(None, value).unwrap()).collect();
+        let sealed_before = secrets.file.read().unwrap().secrets[&ids[0].0].data.to_owned();
+
+        assert!(secrets.change_passphrase("wrong", "second").is_err());
+        secrets.change_passphrase("first", "second").unwrap();
+        assert_ne!(secrets.file.read().unwrap().secrets[&ids[0].0].data, sealed_before);
+
+        secrets.lock().unwrap();
+        assert!(secrets.unlock("first").is_err());
+        secrets.unlock("second").unwrap();
+        let values: Vec<String> = ids.iter().map(|id| secrets.resolve(id).unwrap()).collect();
+        assert_eq!(values, ["one", "two", "three"]);
+    }
+
+    #[test]
+    fn tampered_ciphertext_does_not_open() {
+        let secrets = unlocked("first");
+        let id = secrets.put(None, "s3cr3t").unwrap();
+        {
+            let mut file = secrets.file.write().unwrap();
+            let value = file.secrets.get_mut(&id.0).unwrap();
+            let mut data = decode(&value.data).unwrap();
+            data[0] ^= 1;
+            value.data = BASE64.encode(data);
+        }
+        assert!(secrets.resolve(&id).is_err());
+    }
+
+    #[test]
+    fn secrets_persist_and_a_corrupt_file_is_reported() {
+        let dir = std::env::temp_dir().join(format!("kafka-client-secrets-{}", std::process::id()));
+        let store = SecretStore::load(&dir).unwrap();
+        store.unlock("first").unwrap();
+        let id = store.put(None, "s3cr3t").unwrap();
+
+        let reloaded = SecretStore::load(&dir).unwrap();
+        assert!(reloaded.status().unwrap().initialized);
+        reloaded.unlock("first").unwrap();
+        assert_eq!(reloaded.resolve(&id).unwrap(), "s3cr3t");
+
+        fs::write(dir.join("secrets.json"), "{ not json").unwrap();
+        let err = SecretStore::load(&dir).err().expect("a corrupt file fails to load");
+        let fallback = SecretStore::unavailable(err);
+        assert!(fallback.status().unwrap().load_error.is_some());
+        fallback.unlock("first").unwrap();
+        match fallback.resolve(&id) {
+            Err(AppError::Io(message)) => assert!(message.contains("could not be loaded"), "{}", message),
+            other => panic!("expected the load failure, got {:?}", other),
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
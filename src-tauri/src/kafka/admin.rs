@@ -1,38 +1,51 @@
 use std::{borrow::Borrow, collections::HashMap, ffi::{CStr, CString}, ptr::slice_from_raw_parts, time::Duration};
 use itertools::Itertools;
 use rdkafka::{
-    admin::{AdminClient, AdminOptions, AlterConfig, ConfigEntry, ConfigResource, ConfigSource as KafkaConfigSource, NewTopic, OwnedResourceSpecifier, ResourceSpecifier, TopicReplication, TopicResult}, bindings::{rd_kafka_AdminOptions_new, rd_kafka_ListOffsets, rd_kafka_ListOffsetsResultInfo_topic_partition, rd_kafka_ListOffsets_result_infos, rd_kafka_event_ListOffsets_result, rd_kafka_event_destroy, rd_kafka_event_error, rd_kafka_event_error_string, rd_kafka_queue_destroy, rd_kafka_queue_new, rd_kafka_queue_poll}, client::{Client, DefaultClientContext}, config::FromClientConfig, consumer::{BaseConsumer, CommitMode, Consumer}, error::IsError, statistics::Topic, topic_partition_list::TopicPartitionListElem, types::RDKafkaErrorCode, util::Timeout, ClientConfig, ClientContext, Offset, TopicPartitionList
+    admin::{AdminClient, AdminOptions, AlterConfig, ConfigEntry, ConfigResource, ConfigSource as KafkaConfigSource, NewTopic, OwnedResourceSpecifier, ResourceSpecifier, TopicReplication}, bindings::{rd_kafka_AdminOptions_new, rd_kafka_ListOffsets, rd_kafka_ListOffsetsResultInfo_topic_partition, rd_kafka_ListOffsets_result_infos, rd_kafka_event_ListOffsets_result, rd_kafka_event_destroy, rd_kafka_event_error, rd_kafka_event_error_string, rd_kafka_queue_destroy, rd_kafka_queue_new, rd_kafka_queue_poll}, client::{Client, DefaultClientContext}, config::FromClientConfig, consumer::{BaseConsumer, CommitMode, Consumer}, error::IsError, statistics::Topic, topic_partition_list::TopicPartitionListElem, types::RDKafkaErrorCode, util::Timeout, ClientConfig, ClientContext, Offset, TopicPartitionList
 };
 use serde::{Deserialize, Serialize};
 
-use crate::core::commands::GroupOffset;
+use crate::core::{
+    commands::GroupOffset,
+    config::ClusterConfig,
+    error::{AppError, Resource},
+    secrets::SecretStore,
+};
+
+/// Sets `key` only when neither the cluster overrides nor the operation already did.
+pub fn set_default(config: &mut ClientConfig, key: &str, value: &str) {
+    if config.get(key).is_none() {
+        config.set(key, value);
+    }
+}
+
+pub fn create_admin_client(cluster: &ClusterConfig, secrets: &SecretStore, config: ClientConfig) -> Result<AdminClient<DefaultClientContext>, AppError> {
+    let mut client_config = cluster.client_config(secrets)?;
+    client_config.extend(config.config_map().to_owned());
+    set_default(&mut client_config, "group.id", "runtime");
+    set_default(&mut client_config, "enable.auto.commit", "false");
 
-fn create_admin_client(bootstrap_servers: Vec<String>, config: ClientConfig) -> AdminClient<DefaultClientContext> {
-    AdminClient::from_config(
-        config.to_owned()
-            .set("bootstrap.servers", bootstrap_servers.join(","))
-            .set("group.id", "runtime")
-            .set("enable.auto.commit", "false")
-    )
-    .expect("Error while creating admin client")
+    AdminClient::from_config(&client_config)
+        .map_err(|err| AppError::from(err).on(Resource::Cluster(cluster.name.to_owned())))
 }
 
-fn create_base_consumer(bootstrap_servers: Vec<String>, config: &mut ClientConfig) -> BaseConsumer {
-    config.to_owned()
-        .set("bootstrap.servers", bootstrap_servers.join(","))
+fn create_base_consumer(cluster: &ClusterConfig, secrets: &SecretStore, config: &mut ClientConfig) -> Result<BaseConsumer, AppError> {
+    let mut client_config = cluster.client_config(secrets)?;
+    client_config.extend(config.config_map().to_owned());
+
+    client_config
         .create()
-        .expect("Error creating client")
+        .map_err(|err| AppError::from(err).on(Resource::Cluster(cluster.name.to_owned())))
 }
 
 pub async fn create_topic(
-    bootstrap_servers: Vec<String>,
+    client: &AdminClient<DefaultClientContext>,
     topic: &str,
     partitions: i32,
     replication_factor: i32,
     topic_config: HashMap<String, String>,
     options: Option<AdminOptions>,
-) -> TopicResult {
-    let client = create_admin_client(bootstrap_servers, ClientConfig::default());
+) -> Result<String, AppError> {
     let new_topic = NewTopic {
         config: topic_config.iter()
             .map(|(key, val)| (key.as_str(), val.as_str()))
@@ -47,35 +60,35 @@ pub async fn create_topic(
             vec![new_topic.borrow()],
             options.unwrap_or_default().borrow(),
         )
-        .await
-        .and_then(|val| Ok(val.first().unwrap().to_owned()))
-        .expect("Could not get Result");
-    return out.clone();
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| AppError::internal(format!("No result returned for creating topic '{}'", topic)))?;
+    out.map_err(|(err_str, err_code)| AppError::from_code(err_code, err_str).on(Resource::Topic(topic.to_owned())))
 }
-pub async fn alter_topic_configs(bootstrap_servers: Vec<String>, topic: &str, configs: HashMap<&str, &str>) -> Result<(), String> {
-    let admin = create_admin_client(bootstrap_servers, ClientConfig::default());
+pub async fn alter_topic_configs(admin: &AdminClient<DefaultClientContext>, topic: &str, configs: HashMap<&str, &str>) -> Result<(), AppError> {
     let alter_config = AlterConfig {
         specifier: ResourceSpecifier::Topic(topic),
         entries: configs
     };
     let alter_configs = vec![alter_config];
-    admin.alter_configs(&alter_configs, &AdminOptions::default()).await
-        .map(|_val| ())
-        .map_err(|err| err.to_string())
+    let results = admin.alter_configs(&alter_configs, &AdminOptions::default()).await?;
+    for result in results {
+        result.map_err(|(_, err_code)| AppError::from_code(err_code, "").on(Resource::Topic(topic.to_owned())))?;
+    }
+    Ok(())
 }
 
 
-pub async fn get_topic_configs(bootstrap_servers: Vec<String>, topics:Vec<String>) -> Result<HashMap<String, Vec<ConfigProperty>>, String> {
-    let admin = create_admin_client(bootstrap_servers, ClientConfig::default());    
+pub async fn get_topic_configs(admin: &AdminClient<DefaultClientContext>, topics:Vec<String>) -> Result<HashMap<String, Vec<ConfigProperty>>, AppError> {
     let resource_specifiers: Vec<ResourceSpecifier> = topics.iter().map(|topic| ResourceSpecifier::Topic(topic)).collect();
     let results = admin.describe_configs(&resource_specifiers, &AdminOptions::default())
-        .await
-        .map_err(|err| err.to_string())?;
+        .await?;
 
     
     let configs_list = results.into_iter()
-        .map(|result| result.map_err(|err| err.to_string()))
-        .collect::<Result<Vec<ConfigResource>, String>>()?;
+        .map(|result| result.map_err(|err_code| AppError::from_code(err_code, "")))
+        .collect::<Result<Vec<ConfigResource>, AppError>>()?;
 
 
     let configs = configs_list.into_iter().filter_map(|res| match res.specifier {
@@ -137,69 +150,72 @@ impl From<&KafkaConfigSource> for ConfigSource {
     }
 }
   
-pub async fn delete_topic(bootstrap_servers: Vec<String>, topic: &str) -> Result<String, String> {
+pub async fn delete_topic(admin: &AdminClient<DefaultClientContext>, topic: &str) -> Result<String, AppError> {
     // TODO: make sure topic is not in use by any consumer group assignments
     // if topic_in_use {
     //   return Err(format!("Topic '{}' has partitions assigned to consumer groups"));
     // }
 
-    let admin = create_admin_client(bootstrap_servers, ClientConfig::default());
     let results = admin.delete_topics(&[topic], &AdminOptions::default())
-    .await
-    .map_err(|err| err.to_string())?;
+    .await?;
 
-    let result = results.first().unwrap().to_owned();
+    let result = results
+        .into_iter()
+        .next()
+        .ok_or_else(|| AppError::internal(format!("No result returned for deleting topic '{}'", topic)))?;
         
-    result.map_err(|(err_str, err_code)| format!("[{}]: {}", err_code, err_str))
+    result.map_err(|(err_str, err_code)| AppError::from_code(err_code, err_str).on(Resource::Topic(topic.to_owned())))
 }
 
 
 pub async fn create_consumer_group(
-    bootstrap_servers: Vec<String>,
+    cluster: &ClusterConfig,
+    secrets: &SecretStore,
     group_id: &str,
     topics: Vec<&str>,
     initial_offset: GroupOffset,
-) -> Result<(), String> {
-    let client = create_base_consumer(bootstrap_servers, ClientConfig::default()
+) -> Result<(), AppError> {
+    let client = create_base_consumer(cluster, secrets, ClientConfig::default()
         .set("group.id", group_id)
         .set("enable.auto.offset.store", "false")
-    );
+    )?;
 
     // TODO: Improve this validation by checking committed offsets
-    let all_groups = client.fetch_group_list(None, Timeout::After(Duration::from_secs(5))).map_err(|err| err.to_string())?;
+    let all_groups = client.fetch_group_list(None, Timeout::After(Duration::from_secs(5)))?;
     let group_already_exists = all_groups.groups().iter().any(|g_info| g_info.name() == group_id);
     if group_already_exists {
-        return Err(format!("{} group offsets already exists", group_id));
+        return Err(AppError::invalid(format!("{} group offsets already exists", group_id)));
     }
 
 
     let offsets = get_topics_offsets(client.client(), topics, initial_offset.into(), Offset::End)?;
 
     client.commit(&offsets, CommitMode::Sync)
-        .map_err(|err| err.to_string())
+        .map_err(|err| AppError::from(err).on(Resource::Group(group_id.to_owned())))
 }
-pub async fn delete_consumer_group(bootstrap_servers: Vec<String>, group: &str) -> Result<String, String> {
+pub async fn delete_consumer_group(admin: &AdminClient<DefaultClientContext>, group: &str) -> Result<String, AppError> {
     // TODO: make sure there are no group assignments
     // if active_members_present {
     //   return Err(format!("Topic '{}' has partitions assigned to consumer groups"));
     // }
 
-    let admin = create_admin_client(bootstrap_servers, ClientConfig::default());
     let results = admin.delete_groups(&[group], &AdminOptions::default())
-    .await
-    .map_err(|err: rdkafka::error::KafkaError| err.to_string())?;
+    .await?;
 
-    let result = results.first().unwrap().to_owned();
+    let result = results
+        .into_iter()
+        .next()
+        .ok_or_else(|| AppError::internal(format!("No result returned for deleting group '{}'", group)))?;
         
-    result.map_err(|(err_str, err_code)| format!("[{}]: {}", err_code, err_str))
+    result.map_err(|(err_str, err_code)| AppError::from_code(err_code, err_str).on(Resource::Group(group.to_owned())))
 }
 
-pub fn get_topics_offsets<C: ClientContext>(client: &Client<C>, topics: Vec<&str>, offset: Offset, fallback_offset: Offset) -> Result<TopicPartitionList, String> {
+pub fn get_topics_offsets<C: ClientContext>(client: &Client<C>, topics: Vec<&str>, offset: Offset, fallback_offset: Offset) -> Result<TopicPartitionList, AppError> {
     // Fetch all topic/paritions with latest metadata.
     let mut tpl = TopicPartitionList::new();
     for topic_name in &topics {
         let meta = client.fetch_metadata(Some(topic_name), Timeout::After(Duration::from_secs(5)))
-            .map_err(|err| err.to_string())?;
+            .map_err(|err| AppError::from(err).on(Resource::Topic(topic_name.to_string())))?;
         let topics_meta  = meta.topics().first().filter(|t| t.partitions().len() > 0);
         if let Some(topic) = topics_meta {
             let start_partitions = 0;
@@ -207,7 +223,7 @@ pub fn get_topics_offsets<C: ClientContext>(client: &Client<C>, topics: Vec<&str
             tpl.add_partition_range(topic.name(), start_partitions, total_partitions);
         }
     }
-    let _ = tpl.set_all_offsets(offset).map_err(|err| err.to_string())?;
+    let _ = tpl.set_all_offsets(offset)?;
 
     let offset_list = unsafe {
         get_topic_partition_offsets(client, &tpl)?
@@ -223,7 +239,7 @@ pub fn get_topics_offsets<C: ClientContext>(client: &Client<C>, topics: Vec<&str
    
     // To commit offset maybe just the Offset::End fallback should work. TODO: Confirm this!
     if let Offset::Offset(_) = offset {
-        tpl.set_all_offsets(fallback_offset).map_err(|err| err.to_string())?;
+        tpl.set_all_offsets(fallback_offset)?;
         let fallback_map = unsafe {
             get_topic_partition_offsets(client, &tpl)
             .map(|list| list.to_topic_map())?
@@ -233,8 +249,7 @@ pub fn get_topics_offsets<C: ClientContext>(client: &Client<C>, topics: Vec<&str
         for tpl_el in invalid_offsets {
             let (topic, partition) = (tpl_el.topic(), tpl_el.partition());
             let fallback_offset = fallback_map.get(&(topic.to_string(), partition)).unwrap_or_else(|| &Offset::Invalid);
-            updated_offset_list.set_partition_offset(topic, partition, *fallback_offset)
-                .map_err(|err| err.to_string())?;
+            updated_offset_list.set_partition_offset(topic, partition, *fallback_offset)?;
         }
         return Ok(updated_offset_list);
      } 
@@ -243,7 +258,7 @@ pub fn get_topics_offsets<C: ClientContext>(client: &Client<C>, topics: Vec<&str
 }
 
 
-pub unsafe fn get_topic_partition_offsets<C: ClientContext>(client: &Client<C>, topic_partition_list: &TopicPartitionList) -> Result<TopicPartitionList, String> {
+pub unsafe fn get_topic_partition_offsets<C: ClientContext>(client: &Client<C>, topic_partition_list: &TopicPartitionList) -> Result<TopicPartitionList, AppError> {
     let native_client = client.native_ptr();
     let q = rd_kafka_queue_new(native_client);
     let o = rd_kafka_AdminOptions_new(
@@ -254,20 +269,23 @@ pub unsafe fn get_topic_partition_offsets<C: ClientContext>(client: &Client<C>,
 
     let event = rd_kafka_queue_poll(q, 5000);
     if event.is_null() {
-        return Err(format!("No event received from rd_kafka_queue_poll"));
+        rd_kafka_queue_destroy(q);
+        return Err(AppError::from_code(RDKafkaErrorCode::OperationTimedOut, "Timed out listing partition offsets"));
     }
     let result = rd_kafka_event_ListOffsets_result(event);
     if result.is_null() {
         rd_kafka_event_destroy(event);
         rd_kafka_queue_destroy(q);
-        return Err(format!("No result received from rd_kafka_event_ListOffsets_result"));
+        return Err(AppError::internal("No result received from rd_kafka_event_ListOffsets_result"));
     }
 
     let err = rd_kafka_event_error(event);
     if err.is_error() {
         let msg = rd_kafka_event_error_string(event);
         let err_str = CStr::from_ptr(msg).to_string_lossy().into_owned();
-        return Err(format!("Error: {}", err_str))
+        rd_kafka_event_destroy(event);
+        rd_kafka_queue_destroy(q);
+        return Err(AppError::from_code(err.into(), err_str))
     }
 
     let mut len: usize = 0;
@@ -276,7 +294,7 @@ pub unsafe fn get_topic_partition_offsets<C: ClientContext>(client: &Client<C>,
     if list_offset_result_infos.is_null() {
         rd_kafka_event_destroy(event);
         rd_kafka_queue_destroy(q);
-        return Err(format!("Failed to get list_offset_result_infos"));
+        return Err(AppError::internal("Failed to get list_offset_result_infos"));
     }
 
     let s = &*slice_from_raw_parts(list_offset_result_infos, len);
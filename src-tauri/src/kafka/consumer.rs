@@ -1,13 +1,20 @@
 use byteorder::BigEndian;
 use itertools::Itertools;
 use rdkafka::{
-    bindings::rd_kafka_OffsetSpec_t, client::Client, consumer::{Consumer, DefaultConsumerContext, StreamConsumer}, groups::{GroupInfo,  GroupMemberInfo}, message::{Headers, OwnedMessage}, util::Timeout, ClientConfig, Message, Offset, TopicPartitionList
+    bindings::{rd_kafka_OffsetSpec_t, rd_kafka_get_watermark_offsets}, client::Client, consumer::{Consumer, DefaultConsumerContext, StreamConsumer}, error::KafkaError, groups::{GroupInfo,  GroupMemberInfo}, message::{Headers, OwnedMessage}, types::RDKafkaErrorCode, util::Timeout, Message, Offset, TopicPartitionList
 };
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, io::Cursor, time::Duration};
+use std::{collections::HashMap, ffi::CString, fmt::Display, io::Cursor, time::Duration};
+use tokio::sync::oneshot;
 use byteorder::ReadBytesExt;
 
-use super::{admin::get_topic_partition_offsets, metadata::ClusterMetadata, util::{from_topic_partition_list_to_map, read_str, TopicOffsetsMap}};
+use crate::core::{
+    config::ClusterConfig,
+    error::{AppError, Resource},
+    secrets::SecretStore,
+};
+
+use super::{admin::get_topic_partition_offsets, deserializer::{Decoded, MessageDeserializers}, filter::CompiledFilter, message::{DisplayFormat, MessageBytes, RecordHeader}, metadata::ClusterMetadata, util::{from_topic_partition_list_to_map, read_str, TopicOffsetsMap}};
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct TopicPartitionOffset {
@@ -20,11 +27,228 @@ pub struct MessageEnvelope<K, P> {
     pub key: K,
     pub partition: i32,
     pub offset: i64,
-    pub headers: HashMap<String, String>,
+    /// In the order they were sent, keys may repeat.
+    pub headers: Vec<RecordHeader>,
     pub payload: P,
     pub timestamp: i64,
 }
 
+impl MessageEnvelope<Decoded, Decoded> {
+    /// Bytes of the key, value and headers as received.
+    pub fn size(&self) -> u64 {
+        let size = |bytes: &MessageBytes| bytes.raw.as_ref().map_or(0, |raw| raw.len() as u64);
+        let headers: u64 = self.headers.iter().map(|header| header.key.len() as u64 + size(&header.value)).sum();
+        size(&self.key.bytes) + size(&self.payload.bytes) + headers
+    }
+}
+
+pub enum ConsumedRecord<M = OwnedMessage> {
+    Message(M),
+    /// The partition has no more messages for now.
+    PartitionEof(i32),
+}
+
+/// Emitted on `<consumer id>/error` when reading from a consumer fails.
+#[derive(Clone, Serialize)]
+pub struct ConsumerError {
+    pub error: AppError,
+    /// Whether the consumer gave up, it has then emitted its end of stream as well.
+    pub stopped: bool,
+}
+
+/// How often a consumer emits its `ScanProgress`, at most.
+pub const SCAN_PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Emitted on `<consumer id>/progress` while consuming, also when no message passes the filter.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanProgress {
+    /// Messages read so far, whether they passed the filter or not.
+    pub scanned: u64,
+    pub matched: u64,
+    /// Messages passing the filter that were not emitted because too many were pending.
+    pub dropped: u64,
+    /// Emitted messages the UI has not acknowledged yet.
+    pub pending: u64,
+    /// Whether fetching is paused until the UI catches up.
+    pub paused: bool,
+    /// Read since the previous progress.
+    pub messages_per_second: f64,
+    pub bytes_per_second: f64,
+    /// Next offset to read per partition.
+    pub offsets: HashMap<i32, i64>,
+    /// Messages behind the high watermark per partition, missing until the first fetch response.
+    pub lag: HashMap<i32, i64>,
+}
+
+/// How a consumer hands messages to the UI. Messages are emitted in batches, the UI acknowledges each
+/// batch through `acknowledge_messages` once it is shown.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DeliveryOptions {
+    /// Messages per batch, at most.
+    pub batch_size: usize,
+    /// How long a batch collects messages after its first one before it is emitted.
+    pub batch_window_ms: u64,
+    /// Emitted messages not acknowledged yet, reaching it pauses fetching until half of them are.
+    pub max_pending: u64,
+    /// Drop further messages instead of pausing when `max_pending` is reached, e.g. when tailing.
+    pub drop_when_full: bool,
+}
+
+impl Default for DeliveryOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 500,
+            batch_window_ms: 100,
+            max_pending: 5000,
+            drop_when_full: false,
+        }
+    }
+}
+
+impl DeliveryOptions {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.batch_size == 0 || self.batch_window_ms == 0 {
+            return Err(AppError::invalid("Batches need a size and a window greater than 0"));
+        }
+        if self.max_pending < self.batch_size as u64 {
+            return Err(AppError::invalid(format!(
+                "At least one batch of {} messages must be allowed to be pending",
+                self.batch_size
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn batch_window(&self) -> Duration {
+        Duration::from_millis(self.batch_window_ms)
+    }
+}
+
+/// Where consuming every partition of a topic starts or ends.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", content = "content")]
+pub enum FetchOffset {
+    Beginning,
+    End,
+    Timestamp(i64),
+}
+impl Display for FetchOffset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Beginning => f.write_str("Beginning"),
+            Self::End => f.write_str("End"),
+            Self::Timestamp(t) => f.write_fmt(format_args!("Timestamp({})", t)),
+        }
+    }
+}
+impl Into<Offset> for FetchOffset {
+    fn into(self) -> Offset {
+        match self {
+            Self::End => Offset::End,
+            Self::Beginning => Offset::Beginning,
+            Self::Timestamp(timestamp) => Offset::Offset(timestamp),
+        }
+    }
+}
+
+/// Where consuming a single partition starts.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "content")]
+pub enum PartitionOffset {
+    Beginning,
+    End,
+    Offset(i64),
+    /// The last `n` messages.
+    Tail(i64),
+    /// First message at or after this time, milliseconds since the epoch.
+    Timestamp(i64),
+    /// Offset committed by this consumer group, the beginning when it has none.
+    Committed(String),
+}
+
+/// Sent to a running consumer or export through its `ActiveConsumer`, handled between two messages.
+pub enum ConsumerControl {
+    /// Stop fetching until `Resume`, the session and its position are kept.
+    Pause,
+    Resume,
+    /// Continue the listed partitions from new starts, the offsets they continue from are replied.
+    Seek(HashMap<i32, PartitionOffset>, oneshot::Sender<Result<HashMap<i32, i64>, AppError>>),
+    /// Applies to messages read from now on, `None` lets every message through.
+    ChangeFilter(Option<CompiledFilter>),
+    Stop,
+}
+
+/// When a consumer stops on its own besides its end offsets, the first condition reached ends it.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StopConditions {
+    /// Messages passing the filter.
+    pub max_messages: Option<u64>,
+    /// Stop once every assigned partition was read up to its high watermark.
+    #[serde(default)]
+    pub end_of_partitions: bool,
+    pub max_duration_ms: Option<u64>,
+    /// Stop when no message arrived for this long.
+    pub idle_timeout_ms: Option<u64>,
+    /// Bytes of the messages passing the filter, the message reaching it is still emitted.
+    pub max_bytes: Option<u64>,
+}
+
+impl StopConditions {
+    pub fn validate(&self) -> Result<(), AppError> {
+        let limits = [
+            ("maxMessages", self.max_messages),
+            ("maxDurationMs", self.max_duration_ms),
+            ("idleTimeoutMs", self.idle_timeout_ms),
+            ("maxBytes", self.max_bytes),
+        ];
+        match limits.iter().find(|(_, limit)| *limit == Some(0)) {
+            Some((name, _)) => Err(AppError::invalid(format!("Stop condition '{}' must be greater than 0", name))),
+            None => Ok(()),
+        }
+    }
+
+    pub fn max_duration(&self) -> Option<Duration> {
+        self.max_duration_ms.map(Duration::from_millis)
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout_ms.map(Duration::from_millis)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum StopReason {
+    /// Stopped through `stop_consumer`.
+    Stopped,
+    /// Every partition reached the requested end offset.
+    EndOffsets,
+    MaxMessages,
+    EndOfPartitions,
+    MaxDuration,
+    IdleTimeout,
+    MaxBytes,
+    /// Reading failed, the error was emitted on `<consumer id>/error`.
+    Failed,
+}
+
+/// Emitted once on `<consumer id>/done` when a consumer ends, before its end of stream.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsumerSummary {
+    pub reason: StopReason,
+    pub scanned: u64,
+    pub matched: u64,
+    /// Bytes of the messages passing the filter.
+    pub bytes: u64,
+    pub dropped: u64,
+    /// Next offset to read per partition.
+    pub offsets: HashMap<i32, i64>,
+    pub elapsed_ms: u128,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MemberAssignment {
     pub topic: String,
@@ -113,41 +337,46 @@ pub struct KafkaConsumer {
 }
 
 impl KafkaConsumer {
-    pub fn connect(bootstrap_servers: Vec<String>) -> Self {
-        let config: HashMap<String, String> = HashMap::from([
-            ("bootstrap.servers".into(), bootstrap_servers.join(",")),
+    pub fn connect(cluster: &ClusterConfig, secrets: &SecretStore) -> Result<Self, AppError> {
+        KafkaConsumer::connect_config(cluster, secrets, Self::default_config(cluster))
+    }
+
+    /// Consumer for reading a bounded range, it reports reaching the end of each partition
+    /// as a `ConsumedRecord::PartitionEof`.
+    pub fn connect_bounded(cluster: &ClusterConfig, secrets: &SecretStore) -> Result<Self, AppError> {
+        let mut config = Self::default_config(cluster);
+        config.insert("enable.partition.eof".into(), "true".into());
+        KafkaConsumer::connect_config(cluster, secrets, config)
+    }
+
+    fn default_config(cluster: &ClusterConfig) -> HashMap<String, String> {
+        // Defaults only, the cluster overrides may replace them.
+        HashMap::from([
             ("group.id".into(), "runtime".into()),
             ("enable.auto.commit".into(), "false".into()),
-        ]);
-
-        KafkaConsumer::connect_config(config)
+        ])
+        .into_iter()
+        .filter(|(key, _)| !cluster.client_overrides.contains_key(key))
+        .collect()
     }
 
     pub fn client(&self) -> &Client<DefaultConsumerContext> {
       self.consumer.client()
     }
     
-    pub fn connect_config(config: HashMap<String, String>) -> Self {
-        let mut client_config = ClientConfig::new();
-
-        for (key, value) in config.clone().into_iter() {
-            client_config.set(key, value);
-        }
-
-        Self {
-            consumer:  client_config.create::<StreamConsumer>()
-                .expect(
-                    format!(
-                        "Error while connecting using config {:#?}",
-                        config
-                    )
-                    .as_str(),
-                ),
+    pub fn connect_config(cluster: &ClusterConfig, secrets: &SecretStore, config: HashMap<String, String>) -> Result<Self, AppError> {
+        let mut client_config = cluster.client_config(secrets)?;
+        client_config.extend(config.clone());
+
+        Ok(Self {
+            consumer: client_config
+                .create::<StreamConsumer>()
+                .map_err(|err| AppError::from(err).on(Resource::Cluster(cluster.name.to_owned())))?,
             metadata: None,
-        }
+        })
     }
 
-    pub fn get_metadata(&mut self) -> Result<ClusterMetadata, String> {
+    pub fn get_metadata(&mut self) -> Result<ClusterMetadata, AppError> {
         match &self.metadata {
             Some(metadata) => Result::Ok(metadata.to_owned()),
             None => self
@@ -156,40 +385,39 @@ impl KafkaConsumer {
         }
     }
 
-    pub fn get_committed_offsets(mut self) -> Result<Vec<ConsumerGroupOffsetDescription>, String> {
+    pub fn get_committed_offsets(mut self) -> Result<Vec<ConsumerGroupOffsetDescription>, AppError> {
         let metadata = self.get_metadata()?;
         
         let mut tpl_stored = TopicPartitionList::new();
         for topic in metadata.topics {
             for partition in topic.partitions {
-                tpl_stored.add_partition_offset(topic.name.as_str(), partition.id, Offset::Stored).unwrap();
+                tpl_stored.add_partition_offset(topic.name.as_str(), partition.id, Offset::Stored)?;
             }
         }
 
         let mut tpl_end = tpl_stored.clone();
-        tpl_end.set_all_offsets(Offset::End).unwrap();
+        tpl_end.set_all_offsets(Offset::End)?;
         let end_offsets = unsafe {
             get_topic_partition_offsets(self.consumer.client(), &tpl_end)
             .map(from_topic_partition_list_to_map)?
         };
 
         let mut tpl_beginning = tpl_stored.clone();
-        tpl_beginning.set_all_offsets(Offset::Beginning).unwrap();
+        tpl_beginning.set_all_offsets(Offset::Beginning)?;
         let start_offsets = unsafe {
             get_topic_partition_offsets(self.consumer.client(), &tpl_beginning)
             .map(from_topic_partition_list_to_map)?
         };
 
         let stored_offset =  self.consumer.committed_offsets(tpl_stored, Timeout::Never)
-                            .map_err(|err| err.to_string())
                             .map(from_topic_partition_list_to_map)?;
 
         Ok(from_offset_map_tuple_to_description_vec(start_offsets, end_offsets, stored_offset))
     }
 
-    pub fn get_groups_list(&self) -> Result<Vec<ConsumerGroup>, String> {
+    pub fn get_groups_list(&self) -> Result<Vec<ConsumerGroup>, AppError> {
         self.consumer.fetch_group_list(None, Timeout::After(Duration::from_secs(300)))
-        .map_err(|err| err.to_string())
+        .map_err(AppError::from)
         .map(|group_list| group_list.groups().into_iter().map(|group| ConsumerGroup::from(group)).collect())
     }
 
@@ -198,98 +426,299 @@ impl KafkaConsumer {
         metadata
     }
 
-    fn fetch_metadata(&self) -> Result<ClusterMetadata, String> {
+    pub fn fetch_metadata(&self) -> Result<ClusterMetadata, AppError> {
         self.consumer
             .fetch_metadata(None, Duration::from_secs(2))
             .map(|data| ClusterMetadata::from(&data))
-            .map_err(|err| err.to_string())
+            .map_err(AppError::from)
     }
 
     pub async fn assign_offsets_by_timestamp(
         &mut self,
         topic: &str,
         offset: Offset,
-    ) -> Result<TopicOffsetsMap, String> {
+    ) -> Result<TopicOffsetsMap, AppError> {
         let mut start_offset_timestamp_list = TopicPartitionList::new();
-        let topic_partitions = self
-            .get_metadata()
-            .map(|metadata| {
-                metadata.topics.into_iter().find_map(|item| {
-                    if item.name == topic {
-                        Some(item.partitions)
-                    } else {
-                        None
-                    }
-                })
-            })
-            .and_then(|top| top.ok_or_else(|| "Invalid topic name".to_owned()) )
-            .map_err(|err| err.to_string())?;
-
-        for partition in topic_partitions {
+        for partition in self.topic_partitions(topic)? {
             start_offset_timestamp_list
-                .add_partition_offset(topic, partition.id, offset)
-                .map_err(|err| err.to_string())?;
+                .add_partition_offset(topic, partition, offset)?;
         }
 
         let start_offsets_list = match offset {
             Offset::Offset(_) => self
               .consumer
               .offsets_for_times(start_offset_timestamp_list, Duration::from_secs(2))
-              .map_err(|err| err.to_string())?,
+              .map_err(|err| AppError::from(err).on(Resource::Topic(topic.to_owned())))?,
             Offset::Beginning => unsafe {get_topic_partition_offsets(self.consumer.client(), &start_offset_timestamp_list)?},
             Offset::End => unsafe {get_topic_partition_offsets(self.consumer.client(), &start_offset_timestamp_list)?},
-            _ => panic!("unsupported Offset type {:?}", offset),
+            _ => return Err(AppError::invalid(format!("Unsupported start offset {:?}", offset))),
         };
 
         self.consumer
             .assign(&start_offsets_list)
-            .map_err(|err| err.to_string())?;
+            .map_err(|err| AppError::from(err).on(Resource::Topic(topic.to_owned())))?;
 
         Ok(from_topic_partition_list_to_map(start_offsets_list))
     }
 
-    pub async fn get_next_message(&self) -> Result<MessageEnvelope<String, String>, String> {
+    /// Assigns only the partitions in `starts`, each from its own start.
+    pub async fn assign_partition_offsets(
+        &mut self,
+        cluster: &ClusterConfig,
+        secrets: &SecretStore,
+        topic: &str,
+        starts: &HashMap<i32, PartitionOffset>,
+    ) -> Result<TopicOffsetsMap, AppError> {
+        let mut assignment = TopicPartitionList::new();
+        for (partition, offset) in self.resolve_partition_offsets(cluster, secrets, topic, starts)? {
+            assignment.add_partition_offset(topic, partition, Offset::Offset(offset))?;
+        }
+
         self.consumer
-            .recv()
-            .await
-            .map(|x| x.detach())
-            .map(Self::convert_message)
-            .map_err(|err| err.to_string())
-    }
-
-    fn convert_message(message: OwnedMessage) -> MessageEnvelope<String, String> {
-        let key = message
-            .key()
-            .map(String::from_utf8_lossy)
-            .unwrap_or_default()
-            .to_string();
-        let timestamp = message.timestamp().to_millis().unwrap_or_default();
+            .assign(&assignment)
+            .map_err(|err| AppError::from(err).on(Resource::Topic(topic.to_owned())))?;
+
+        Ok(from_topic_partition_list_to_map(assignment))
+    }
+
+    /// Moves assigned partitions of a running consumer to new starts, returning the offsets they continue from.
+    pub fn seek_partition_offsets(
+        &mut self,
+        cluster: &ClusterConfig,
+        secrets: &SecretStore,
+        topic: &str,
+        starts: &HashMap<i32, PartitionOffset>,
+    ) -> Result<HashMap<i32, i64>, AppError> {
+        let assigned = self.consumer.assignment()?;
+        if let Some(partition) = starts.keys().find(|partition| assigned.find_partition(topic, **partition).is_none()) {
+            return Err(AppError::invalid(format!("Partition {} of '{}' is not consumed", partition, topic)));
+        }
+        let offsets = self.resolve_partition_offsets(cluster, secrets, topic, starts)?;
+
+        let mut positions = TopicPartitionList::new();
+        for (partition, offset) in &offsets {
+            positions.add_partition_offset(topic, *partition, Offset::Offset(*offset))?;
+        }
+        let sought = self
+            .consumer
+            .seek_partitions(positions, Duration::from_secs(5))
+            .map_err(|err| AppError::from(err).on(Resource::Topic(topic.to_owned())))?;
+        for element in sought.elements() {
+            element.error().map_err(|err| {
+                AppError::from(err).on(Resource::Partition { topic: topic.to_owned(), partition: element.partition() })
+            })?;
+        }
+        Ok(offsets)
+    }
+
+    /// Offsets the partitions in `starts` begin at, kept within each partition's current range.
+    /// Committed offsets are read with a consumer of that group.
+    fn resolve_partition_offsets(
+        &mut self,
+        cluster: &ClusterConfig,
+        secrets: &SecretStore,
+        topic: &str,
+        starts: &HashMap<i32, PartitionOffset>,
+    ) -> Result<HashMap<i32, i64>, AppError> {
+        if starts.is_empty() {
+            return Err(AppError::invalid("No partitions given"));
+        }
+        let known = self.topic_partitions(topic)?;
+        if let Some(partition) = starts.keys().find(|partition| !known.contains(partition)) {
+            return Err(AppError::from_code(
+                RDKafkaErrorCode::UnknownTopicOrPartition,
+                format!("Topic '{}' has no partition {}", topic, partition),
+            )
+            .on(Resource::Partition { topic: topic.to_owned(), partition: *partition }));
+        }
+
+        let low = self.partition_offsets(topic, starts.keys(), Offset::Beginning)?;
+        let high = self.partition_offsets(topic, starts.keys(), Offset::End)?;
+
+        let mut timestamps = TopicPartitionList::new();
+        for (partition, start) in starts {
+            if let PartitionOffset::Timestamp(timestamp) = start {
+                timestamps.add_partition_offset(topic, *partition, Offset::Offset(*timestamp))?;
+            }
+        }
+        let by_timestamp = match timestamps.count() {
+            0 => HashMap::new(),
+            _ => self
+                .consumer
+                .offsets_for_times(timestamps, Duration::from_secs(2))
+                .map_err(|err| AppError::from(err).on(Resource::Topic(topic.to_owned())))?
+                .to_topic_map(),
+        };
+
+        let mut committed = HashMap::new();
+        for group in starts.values().filter_map(|start| match start {
+            PartitionOffset::Committed(group) => Some(group),
+            _ => None,
+        }) {
+            if committed.contains_key(group) {
+                continue;
+            }
+            let group_consumer =
+                KafkaConsumer::connect_config(cluster, secrets, HashMap::from([("group.id".to_owned(), group.to_owned())]))?;
+            let offsets = group_consumer
+                .committed_partition_offsets(topic, starts.keys())
+                .map_err(|err| err.on(Resource::Group(group.to_owned())))?;
+            committed.insert(group.to_owned(), offsets);
+        }
+
+        let mut offsets = HashMap::new();
+        for (partition, start) in starts {
+            let (Some(&low), Some(&high)) = (low.get(partition), high.get(partition)) else {
+                return Err(AppError::from_code(
+                    RDKafkaErrorCode::OffsetNotAvailable,
+                    format!("Offsets of partition {} of '{}' are not available", partition, topic),
+                )
+                .on(Resource::Partition { topic: topic.to_owned(), partition: *partition }));
+            };
+            let offset = match start {
+                PartitionOffset::Beginning => low,
+                PartitionOffset::End => high,
+                PartitionOffset::Offset(offset) => *offset,
+                PartitionOffset::Tail(count) => high - (*count).max(0),
+                PartitionOffset::Timestamp(_) => match by_timestamp.get(&(topic.to_owned(), *partition)) {
+                    Some(Offset::Offset(offset)) => *offset,
+                    // No message at or after the timestamp yet.
+                    _ => high,
+                },
+                PartitionOffset::Committed(group) => committed[group].get(partition).copied().unwrap_or(low),
+            };
+            offsets.insert(*partition, offset.clamp(low, high));
+        }
+        Ok(offsets)
+    }
+
+    fn topic_partitions(&mut self, topic: &str) -> Result<Vec<i32>, AppError> {
+        self.get_metadata()
+            .map(|metadata| {
+                metadata
+                    .topics
+                    .into_iter()
+                    .find(|item| item.name == topic)
+                    .map(|item| item.partitions.into_iter().map(|partition| partition.id).collect())
+            })
+            .and_then(|partitions| {
+                partitions.ok_or_else(|| {
+                    AppError::from_code(RDKafkaErrorCode::UnknownTopicOrPartition, format!("Unknown topic '{}'", topic))
+                        .on(Resource::Topic(topic.to_owned()))
+                })
+            })
+    }
+
+    /// Earliest or latest offset of each partition.
+    fn partition_offsets<'a>(
+        &self,
+        topic: &str,
+        partitions: impl Iterator<Item = &'a i32>,
+        offset: Offset,
+    ) -> Result<HashMap<i32, i64>, AppError> {
+        let mut list = TopicPartitionList::new();
+        for partition in partitions {
+            list.add_partition_offset(topic, *partition, offset)?;
+        }
+        let offsets = unsafe { get_topic_partition_offsets(self.consumer.client(), &list)? };
+        Ok(from_topic_partition_list_to_map(offsets).remove(topic).unwrap_or_default().into_iter().collect())
+    }
+
+    /// Offsets committed by the group of this consumer, partitions without one are left out.
+    fn committed_partition_offsets<'a>(
+        &self,
+        topic: &str,
+        partitions: impl Iterator<Item = &'a i32>,
+    ) -> Result<HashMap<i32, i64>, AppError> {
+        let mut list = TopicPartitionList::new();
+        for partition in partitions {
+            list.add_partition_offset(topic, *partition, Offset::Stored)?;
+        }
+        let committed = self.consumer.committed_offsets(list, Duration::from_secs(10))?;
+        Ok(from_topic_partition_list_to_map(committed).remove(topic).unwrap_or_default().into_iter().collect())
+    }
+
+    /// Stops fetching from every assigned partition, reading resumes where it stopped.
+    pub fn pause(&self) -> Result<(), AppError> {
+        self.consumer.pause(&self.consumer.assignment()?).map_err(AppError::from)
+    }
+
+    pub fn resume(&self) -> Result<(), AppError> {
+        self.consumer.resume(&self.consumer.assignment()?).map_err(AppError::from)
+    }
+
+    /// Messages between `offsets`, the next ones to read, and the high watermarks last seen by the fetcher.
+    /// No request is sent, partitions without a fetch response yet are left out.
+    pub fn lag(&self, topic: &str, offsets: &HashMap<i32, i64>) -> HashMap<i32, i64> {
+        let Ok(topic) = CString::new(topic) else {
+            return HashMap::new();
+        };
+        offsets
+            .iter()
+            .filter_map(|(partition, offset)| {
+                let (mut low, mut high) = (-1, -1);
+                unsafe {
+                    rd_kafka_get_watermark_offsets(
+                        self.consumer.client().native_ptr(),
+                        topic.as_ptr(),
+                        *partition,
+                        &mut low,
+                        &mut high,
+                    );
+                }
+                (high >= 0).then(|| (*partition, (high - offset).max(0)))
+            })
+            .collect()
+    }
+
+    /// `message` with its key and value run through `deserializers`.
+    ///
+    /// Decoding may fetch schemas from a registry, so it runs apart from `get_next_record` which is safe to cancel.
+    pub async fn decode_message<M: Message>(
+        message: &M,
+        display: DisplayFormat,
+        deserializers: &MessageDeserializers,
+    ) -> MessageEnvelope<Decoded, Decoded> {
+        let envelope = Self::convert_message(message, display);
+        MessageEnvelope {
+            key: deserializers.key(envelope.key).await,
+            partition: envelope.partition,
+            offset: envelope.offset,
+            headers: envelope.headers,
+            payload: deserializers.value(envelope.payload).await,
+            timestamp: envelope.timestamp,
+        }
+    }
+
+    /// Next message with its raw bytes untouched.
+    pub async fn get_next_record(&self) -> Result<ConsumedRecord, AppError> {
+        match self.consumer.recv().await {
+            Ok(message) => Ok(ConsumedRecord::Message(message.detach())),
+            Err(KafkaError::PartitionEOF(partition)) => Ok(ConsumedRecord::PartitionEof(partition)),
+            Err(err) => Err(AppError::from(err)),
+        }
+    }
+
+    pub fn convert_message<M: Message>(message: &M, display: DisplayFormat) -> MessageEnvelope<MessageBytes, MessageBytes> {
         let headers = message
             .headers()
-            .map(|h| {
-                let mut map = HashMap::with_capacity(h.count());
-                for i in 0..h.count() {
-                    let header = h.get(i);
-                    let key = header.key;
-                    let val = header.value.unwrap_or_else(|| &[] as &[u8] );
-
-                    map.insert(key.to_string(), String::from_utf8_lossy(val).to_string());
-                }
-                map
+            .map(|headers| {
+                headers
+                    .iter()
+                    .map(|header| RecordHeader {
+                        key: header.key.to_owned(),
+                        value: MessageBytes::new(header.value, display),
+                    })
+                    .collect()
             })
             .unwrap_or_default();
-        let payload = message
-            .payload()
-            .map(String::from_utf8_lossy)
-            .map(|v| v.to_string())
-            .unwrap_or_default();
         MessageEnvelope {
-            key,
+            key: MessageBytes::new(message.key(), display),
             partition: message.partition(),
             offset: message.offset(),
             headers,
-            payload,
-            timestamp,
+            payload: MessageBytes::new(message.payload(), display),
+            timestamp: message.timestamp().to_millis().unwrap_or_default(),
         }
     }
 }
@@ -0,0 +1,338 @@
+use std::{fs, sync::Arc};
+
+use apache_avro::{from_avro_datum_schemata, Schema};
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::core::error::AppError;
+
+use super::{message::MessageBytes, schema_registry::SchemaRegistryClient};
+
+/// Format of the keys or values of a topic, remembered per topic in the cluster config.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "content")]
+pub enum DataFormat {
+    /// UTF-8 text, invalid sequences are replaced.
+    String,
+    Json,
+    /// Avro binary encoding of a single datum written with `schema`.
+    Avro { schema: String },
+    /// `message` of a `FileDescriptorSet` file as written by `protoc --include_imports --descriptor_set_out`.
+    Protobuf { descriptor_set: String, message: String },
+    MessagePack,
+    /// Confluent wire format, the schema is looked up by its id in the schema registry of the cluster.
+    SchemaRegistry,
+    /// Big endian 32 bit integer.
+    Int,
+    /// Big endian 64 bit integer.
+    Long,
+    /// 16 bytes, or the hyphenated text form.
+    Uuid,
+}
+
+impl DataFormat {
+    /// Builds the deserializer, parsing or loading the schema once. Schema registry lookups are
+    /// asynchronous, `MessageDeserializers` resolves those.
+    pub fn deserializer(&self) -> Result<Arc<dyn Deserializer>, AppError> {
+        Ok(match self {
+            Self::SchemaRegistry => {
+                return Err(AppError::invalid("The schema registry format needs the registry of a cluster"))
+            }
+            Self::String => Arc::new(StringDeserializer),
+            Self::Json => Arc::new(JsonDeserializer),
+            Self::Avro { schema } => Arc::new(AvroDeserializer::new(schema)?),
+            Self::Protobuf { descriptor_set, message } => Arc::new(ProtobufDeserializer::load(descriptor_set, message)?),
+            Self::MessagePack => Arc::new(MessagePackDeserializer),
+            Self::Int => Arc::new(IntDeserializer),
+            Self::Long => Arc::new(LongDeserializer),
+            Self::Uuid => Arc::new(UuidDeserializer),
+        })
+    }
+}
+
+/// Formats of the key and value of a topic, unset ones are left as raw bytes.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TopicFormats {
+    pub key: Option<DataFormat>,
+    pub value: Option<DataFormat>,
+}
+
+/// Turns the raw bytes of a key or value into a value the UI can show.
+pub trait Deserializer: Send + Sync {
+    fn deserialize(&self, bytes: &[u8]) -> Result<Value, AppError>;
+}
+
+pub struct StringDeserializer;
+impl Deserializer for StringDeserializer {
+    fn deserialize(&self, bytes: &[u8]) -> Result<Value, AppError> {
+        Ok(Value::String(String::from_utf8_lossy(bytes).into_owned()))
+    }
+}
+
+pub struct JsonDeserializer;
+impl Deserializer for JsonDeserializer {
+    fn deserialize(&self, bytes: &[u8]) -> Result<Value, AppError> {
+        serde_json::from_slice(bytes).map_err(|err| AppError::invalid(format!("Not valid JSON: {}", err)))
+    }
+}
+
+pub struct AvroDeserializer {
+    schema: Schema,
+    /// Schemas defining the named types `schema` uses but does not define itself.
+    references: Vec<Schema>,
+}
+impl AvroDeserializer {
+    pub fn new(schema: &str) -> Result<Self, AppError> {
+        Self::with_references(&[schema])
+    }
+
+    /// `definitions` end with the schema of the data, the ones before it define the types it references.
+    pub fn with_references(definitions: &[&str]) -> Result<Self, AppError> {
+        let mut schemas =
+            Schema::parse_list(definitions).map_err(|err| AppError::invalid(format!("Invalid Avro schema: {}", err)))?;
+        let schema = schemas.pop().ok_or_else(|| AppError::invalid("Avro schema is missing"))?;
+        Ok(Self {
+            schema,
+            references: schemas,
+        })
+    }
+}
+impl Deserializer for AvroDeserializer {
+    fn deserialize(&self, bytes: &[u8]) -> Result<Value, AppError> {
+        let datum = from_avro_datum_schemata(&self.schema, self.references.iter().collect(), &mut &bytes[..], None)
+            .map_err(|err| AppError::invalid(format!("Not valid Avro for the schema: {}", err)))?;
+        Value::try_from(datum).map_err(|err| AppError::invalid(format!("Avro value has no JSON form: {}", err)))
+    }
+}
+
+pub struct ProtobufDeserializer {
+    message: MessageDescriptor,
+}
+impl ProtobufDeserializer {
+    pub fn load(descriptor_set: &str, message: &str) -> Result<Self, AppError> {
+        let bytes = fs::read(descriptor_set)
+            .map_err(|err| AppError::Io(format!("Could not read descriptor set '{}': {}", descriptor_set, err)))?;
+        let pool = DescriptorPool::decode(bytes.as_slice())
+            .map_err(|err| AppError::invalid(format!("Invalid descriptor set '{}': {}", descriptor_set, err)))?;
+        Self::from_pool(&pool, message)
+    }
+
+    pub fn from_pool(pool: &DescriptorPool, message: &str) -> Result<Self, AppError> {
+        Ok(Self {
+            message: pool
+                .get_message_by_name(message)
+                .ok_or_else(|| AppError::invalid(format!("Protobuf message '{}' is not defined", message)))?,
+        })
+    }
+}
+impl Deserializer for ProtobufDeserializer {
+    fn deserialize(&self, bytes: &[u8]) -> Result<Value, AppError> {
+        let message = DynamicMessage::decode(self.message.to_owned(), bytes).map_err(|err| {
+            AppError::invalid(format!("Not a valid '{}' message: {}", self.message.full_name(), err))
+        })?;
+        serde_json::to_value(&message).map_err(|err| AppError::internal(err.to_string()))
+    }
+}
+
+pub struct MessagePackDeserializer;
+impl Deserializer for MessagePackDeserializer {
+    fn deserialize(&self, bytes: &[u8]) -> Result<Value, AppError> {
+        rmp_serde::from_slice(bytes).map_err(|err| AppError::invalid(format!("Not valid MessagePack: {}", err)))
+    }
+}
+
+pub struct IntDeserializer;
+impl Deserializer for IntDeserializer {
+    fn deserialize(&self, bytes: &[u8]) -> Result<Value, AppError> {
+        let bytes = <[u8; 4]>::try_from(bytes)
+            .map_err(|_| AppError::invalid(format!("An int needs 4 bytes, got {}", bytes.len())))?;
+        Ok(Value::from(i32::from_be_bytes(bytes)))
+    }
+}
+
+pub struct LongDeserializer;
+impl Deserializer for LongDeserializer {
+    fn deserialize(&self, bytes: &[u8]) -> Result<Value, AppError> {
+        let bytes = <[u8; 8]>::try_from(bytes)
+            .map_err(|_| AppError::invalid(format!("A long needs 8 bytes, got {}", bytes.len())))?;
+        Ok(Value::from(i64::from_be_bytes(bytes)))
+    }
+}
+
+pub struct UuidDeserializer;
+impl Deserializer for UuidDeserializer {
+    fn deserialize(&self, bytes: &[u8]) -> Result<Value, AppError> {
+        let uuid = match bytes.len() {
+            16 => Uuid::from_slice(bytes).map_err(|err| AppError::invalid(err.to_string())),
+            _ => std::str::from_utf8(bytes)
+                .ok()
+                .and_then(|text| Uuid::try_parse(text).ok())
+                .ok_or_else(|| AppError::invalid(format!("Not a UUID, got {} bytes", bytes.len()))),
+        }?;
+        Ok(Value::String(uuid.hyphenated().to_string()))
+    }
+}
+
+/// Key or payload of a received message, deserialized next to its raw bytes.
+#[derive(Clone, Debug, Serialize)]
+pub struct Decoded {
+    #[serde(flatten)]
+    pub bytes: MessageBytes,
+    /// `None` for null, when the topic has no format set, or when deserializing failed.
+    pub value: Option<Value>,
+    pub error: Option<AppError>,
+}
+
+#[derive(Clone)]
+enum Decoder {
+    Plain(Arc<dyn Deserializer>),
+    Registry(Arc<SchemaRegistryClient>),
+}
+
+impl Decoder {
+    fn new(format: &DataFormat, registry: Option<&Arc<SchemaRegistryClient>>) -> Result<Self, AppError> {
+        match format {
+            DataFormat::SchemaRegistry => registry
+                .cloned()
+                .map(Self::Registry)
+                .ok_or_else(|| AppError::invalid("The cluster has no schema registry configured")),
+            format => format.deserializer().map(Self::Plain),
+        }
+    }
+
+    async fn deserialize(&self, bytes: &[u8]) -> Result<Value, AppError> {
+        match self {
+            Self::Plain(deserializer) => deserializer.deserialize(bytes),
+            Self::Registry(registry) => registry.decode(bytes).await,
+        }
+    }
+}
+
+/// Deserializers applied to the keys and values of one topic.
+#[derive(Clone, Default)]
+pub struct MessageDeserializers {
+    key: Option<Decoder>,
+    value: Option<Decoder>,
+}
+
+impl MessageDeserializers {
+    /// `registry` is only needed for the `SchemaRegistry` format.
+    pub fn new(formats: &TopicFormats, registry: Option<Arc<SchemaRegistryClient>>) -> Result<Self, AppError> {
+        let build = |format: &Option<DataFormat>| {
+            format
+                .as_ref()
+                .map(|format| Decoder::new(format, registry.as_ref()))
+                .transpose()
+        };
+        Ok(Self {
+            key: build(&formats.key).map_err(|err| err.context("Key format"))?,
+            value: build(&formats.value).map_err(|err| err.context("Value format"))?,
+        })
+    }
+
+    pub async fn key(&self, bytes: MessageBytes) -> Decoded {
+        decode(bytes, self.key.as_ref()).await
+    }
+
+    pub async fn value(&self, bytes: MessageBytes) -> Decoded {
+        decode(bytes, self.value.as_ref()).await
+    }
+}
+
+async fn decode(bytes: MessageBytes, decoder: Option<&Decoder>) -> Decoded {
+    let result = match (bytes.raw.as_deref(), decoder) {
+        (Some(raw), Some(decoder)) => decoder.deserialize(raw).await.map(Some),
+        _ => Ok(None),
+    };
+    match result {
+        Ok(value) => Decoded { bytes, value, error: None },
+        Err(error) => Decoded {
+            bytes,
+            value: None,
+            error: Some(error),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{DataFormat, MessageDeserializers, TopicFormats};
+    use crate::kafka::message::{DisplayFormat, MessageBytes};
+
+    fn deserialize(format: DataFormat, bytes: &[u8]) -> Result<serde_json::Value, crate::core::error::AppError> {
+        format.deserializer().unwrap().deserialize(bytes)
+    }
+
+    #[test]
+    fn strings_replace_invalid_utf8() {
+        assert_eq!(deserialize(DataFormat::String, "héllo".as_bytes()).unwrap(), json!("héllo"));
+        assert_eq!(deserialize(DataFormat::String, &[b'a', 0xff]).unwrap(), json!("a\u{fffd}"));
+    }
+
+    #[test]
+    fn json_and_messagepack_become_json_values() {
+        assert_eq!(deserialize(DataFormat::Json, br#"{"a": [1, null]}"#).unwrap(), json!({"a": [1, null]}));
+        assert!(deserialize(DataFormat::Json, b"{").is_err());
+
+        let packed = rmp_serde::to_vec_named(&json!({"id": 7, "tags": ["x"]})).unwrap();
+        assert_eq!(deserialize(DataFormat::MessagePack, &packed).unwrap(), json!({"id": 7, "tags": ["x"]}));
+        assert!(deserialize(DataFormat::MessagePack, &[0xc1]).is_err(), "0xc1 is never used");
+    }
+
+    #[test]
+    fn numbers_are_big_endian_and_need_their_exact_length() {
+        assert_eq!(deserialize(DataFormat::Int, &(-2i32).to_be_bytes()).unwrap(), json!(-2));
+        assert_eq!(deserialize(DataFormat::Long, &i64::MAX.to_be_bytes()).unwrap(), json!(i64::MAX));
+        for bytes in [&[][..], &[1, 2, 3], &[0; 5]] {
+            assert!(deserialize(DataFormat::Int, bytes).is_err(), "{} bytes", bytes.len());
+        }
+        for bytes in [&[][..], &[0; 4], &[0; 9]] {
+            assert!(deserialize(DataFormat::Long, bytes).is_err(), "{} bytes", bytes.len());
+        }
+    }
+
+    #[test]
+    fn uuids_are_read_from_bytes_or_text() {
+        let uuid = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+        let bytes = uuid::Uuid::parse_str(uuid).unwrap().into_bytes();
+        assert_eq!(deserialize(DataFormat::Uuid, &bytes).unwrap(), json!(uuid));
+        assert_eq!(deserialize(DataFormat::Uuid, uuid.as_bytes()).unwrap(), json!(uuid));
+        assert_eq!(deserialize(DataFormat::Uuid, uuid.to_uppercase().as_bytes()).unwrap(), json!(uuid));
+        for bytes in [&[][..], &bytes[..15], &[0; 17], &[0xff; 36]] {
+            assert!(deserialize(DataFormat::Uuid, bytes).is_err(), "{} bytes", bytes.len());
+        }
+    }
+
+    #[tokio::test]
+    async fn failures_keep_the_raw_bytes() {
+        let formats = TopicFormats {
+            key: Some(DataFormat::Long),
+            value: Some(DataFormat::Int),
+        };
+        let deserializers = MessageDeserializers::new(&formats, None).unwrap();
+
+        let key = deserializers.key(MessageBytes::new(Some(&[1, 2, 3]), DisplayFormat::Hex)).await;
+        assert_eq!(key.value, None);
+        assert!(key.error.is_some());
+        assert_eq!((key.bytes.raw.as_deref(), key.bytes.text.as_deref()), (Some(&[1u8, 2, 3][..]), Some("010203")));
+
+        let value = deserializers.value(MessageBytes::new(Some(&7i32.to_be_bytes()), DisplayFormat::Utf8)).await;
+        assert_eq!((value.value, value.error.is_none()), (Some(json!(7)), true));
+
+        let null = deserializers.value(MessageBytes::new(None, DisplayFormat::Utf8)).await;
+        assert_eq!((null.value, null.error.is_none()), (None, true), "null is not an error");
+    }
+
+    #[test]
+    fn schema_registry_format_needs_a_registry() {
+        let formats = TopicFormats {
+            key: None,
+            value: Some(DataFormat::SchemaRegistry),
+        };
+        assert!(MessageDeserializers::new(&formats, None).is_err());
+    }
+}
@@ -0,0 +1,408 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use byteorder::{BigEndian, WriteBytesExt};
+use rdkafka::{
+    message::{Headers, OwnedMessage},
+    Message, Offset,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::core::error::AppError;
+
+use super::{
+    admin::get_topics_offsets,
+    consumer::{ConsumedRecord, ConsumerControl, KafkaConsumer},
+    message::DisplayFormat,
+    util::from_topic_partition_list_to_map,
+};
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+/// Start of every binary export, the trailing byte is the layout version.
+pub const BINARY_MAGIC: &[u8] = b"KRAW\x01";
+const CSV_COLUMNS: [&str; 6] = ["partition", "offset", "timestamp", "key", "value", "headers"];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "content")]
+pub enum ExportFormat {
+    /// One `MessageEnvelope` per line with the raw bytes, readable by `produce_from_file`.
+    Jsonl,
+    /// Keys, values and headers decoded as UTF-8, headers as a JSON array of `[key, value]` pairs.
+    /// Null and empty can not be told apart.
+    Csv,
+    /// Raw bytes of every message, nothing is decoded. After `BINARY_MAGIC` each record is
+    /// `partition:i32 offset:i64 timestamp:i64 key:bytes value:bytes header_count:i32`
+    /// followed by `header_count` pairs of `name:bytes value:bytes`. `bytes` is an `i32`
+    /// length and the data, a length of `-1` is null. All integers are big endian.
+    Binary,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportProgress {
+    pub path: String,
+    pub topic: String,
+    pub written: u64,
+    /// Partitions that have not reached their end offset yet.
+    pub remaining_partitions: Vec<i32>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportSummary {
+    pub path: String,
+    pub topic: String,
+    pub written: u64,
+    /// Whether the export was stopped before reaching the end offsets.
+    pub stopped: bool,
+    /// The error that ended the export, messages written before it are kept.
+    pub error: Option<AppError>,
+    pub elapsed_ms: u128,
+}
+
+enum Sink {
+    Jsonl(BufWriter<File>),
+    Csv(csv::Writer<File>),
+    Binary(BufWriter<File>),
+}
+
+pub struct ExportWriter {
+    path: PathBuf,
+    sink: Sink,
+}
+
+impl ExportWriter {
+    /// Creates `path`, replacing an existing file.
+    pub fn create(path: &Path, format: &ExportFormat) -> Result<Self, AppError> {
+        let file = File::create(path)
+            .map_err(|err| AppError::Io(format!("Could not create '{}': {}", path.display(), err)))?;
+        let mut writer = Self {
+            path: path.to_owned(),
+            sink: match format {
+                ExportFormat::Jsonl => Sink::Jsonl(BufWriter::new(file)),
+                ExportFormat::Csv => Sink::Csv(csv::Writer::from_writer(file)),
+                ExportFormat::Binary => Sink::Binary(BufWriter::new(file)),
+            },
+        };
+
+        match &mut writer.sink {
+            Sink::Jsonl(_) => Ok(()),
+            Sink::Csv(csv) => csv.write_record(CSV_COLUMNS).map_err(|err| writer.csv_error(err)),
+            Sink::Binary(out) => out.write_all(BINARY_MAGIC).map_err(|err| writer.io_error(err)),
+        }?;
+        Ok(writer)
+    }
+
+    pub fn write(&mut self, message: &OwnedMessage) -> Result<(), AppError> {
+        let result = match &mut self.sink {
+            Sink::Jsonl(out) => {
+                let envelope = KafkaConsumer::convert_message(message, DisplayFormat::Utf8);
+                serde_json::to_writer(&mut *out, &envelope)
+                    .map_err(std::io::Error::from)
+                    .and_then(|_| out.write_all(b"\n"))
+            }
+            Sink::Csv(csv) => {
+                let envelope = KafkaConsumer::convert_message(message, DisplayFormat::Utf8);
+                let headers: Vec<(String, Option<String>)> = envelope
+                    .headers
+                    .into_iter()
+                    .map(|header| (header.key, header.value.text))
+                    .collect();
+                let headers = serde_json::to_string(&headers).map_err(|err| AppError::internal(err.to_string()))?;
+                return csv
+                    .write_record([
+                        envelope.partition.to_string(),
+                        envelope.offset.to_string(),
+                        envelope.timestamp.to_string(),
+                        envelope.key.text.unwrap_or_default(),
+                        envelope.payload.text.unwrap_or_default(),
+                        headers,
+                    ])
+                    .map_err(|err| self.csv_error(err));
+            }
+            Sink::Binary(out) => write_binary(out, message),
+        };
+        result.map_err(|err| self.io_error(err))
+    }
+
+    pub fn finish(self) -> Result<(), AppError> {
+        let result = match self.sink {
+            Sink::Jsonl(mut out) | Sink::Binary(mut out) => out.flush(),
+            Sink::Csv(mut csv) => csv.flush(),
+        };
+        result.map_err(|err| AppError::Io(format!("Could not write '{}': {}", self.path.display(), err)))
+    }
+
+    fn io_error(&self, err: std::io::Error) -> AppError {
+        AppError::Io(format!("Could not write '{}': {}", self.path.display(), err))
+    }
+
+    fn csv_error(&self, err: csv::Error) -> AppError {
+        AppError::Io(format!("Could not write '{}': {}", self.path.display(), err))
+    }
+}
+
+fn write_binary(out: &mut impl Write, message: &OwnedMessage) -> std::io::Result<()> {
+    out.write_i32::<BigEndian>(message.partition())?;
+    out.write_i64::<BigEndian>(message.offset())?;
+    out.write_i64::<BigEndian>(message.timestamp().to_millis().unwrap_or(-1))?;
+    write_bytes(out, message.key())?;
+    write_bytes(out, message.payload())?;
+    match message.headers() {
+        Some(headers) => {
+            out.write_i32::<BigEndian>(headers.count() as i32)?;
+            for header in headers.iter() {
+                write_bytes(out, Some(header.key.as_bytes()))?;
+                write_bytes(out, header.value)?;
+            }
+        }
+        None => out.write_i32::<BigEndian>(0)?,
+    }
+    Ok(())
+}
+
+fn write_bytes(out: &mut impl Write, bytes: Option<&[u8]>) -> std::io::Result<()> {
+    match bytes {
+        Some(bytes) => {
+            out.write_i32::<BigEndian>(bytes.len() as i32)?;
+            out.write_all(bytes)
+        }
+        None => out.write_i32::<BigEndian>(-1),
+    }
+}
+
+/// Offsets of `topic` at `end`, per partition. A timestamp past the last message resolves to the end of the partition.
+pub fn end_offsets(consumer: &KafkaConsumer, topic: &str, end: Offset) -> Result<HashMap<i32, i64>, AppError> {
+    let offsets = get_topics_offsets(consumer.client(), vec![topic], end, Offset::End)?;
+    Ok(from_topic_partition_list_to_map(offsets)
+        .remove(topic)
+        .unwrap_or_default()
+        .into_iter()
+        .collect())
+}
+
+/// Writes the messages of `topic` from the `start` offsets the consumer is assigned to up to, not including,
+/// the `end` offsets. Ends early on `ConsumerControl::Stop`, the summary then reports the export as stopped.
+pub async fn export_range(
+    consumer: KafkaConsumer,
+    topic: &str,
+    start: HashMap<i32, i64>,
+    end: HashMap<i32, i64>,
+    mut writer: ExportWriter,
+    control: &mut mpsc::Receiver<ConsumerControl>,
+    mut on_progress: impl FnMut(&ExportProgress),
+) -> ExportSummary {
+    let started = Instant::now();
+    // Partitions missing from `start` had no message after the start timestamp.
+    let mut remaining: HashMap<i32, i64> = end
+        .into_iter()
+        .filter(|(partition, end)| start.get(partition).is_some_and(|start| start < end))
+        .collect();
+    let mut progress = ExportProgress {
+        path: writer.path.display().to_string(),
+        topic: topic.to_owned(),
+        written: 0,
+        remaining_partitions: vec![],
+    };
+    let mut stopped = false;
+    let mut error = None;
+
+    let mut last_progress = Instant::now();
+    while !remaining.is_empty() {
+        let record = tokio::select! {
+            command = control.recv() => {
+                match command {
+                    None | Some(ConsumerControl::Stop) => {
+                        stopped = true;
+                        break;
+                    }
+                    Some(ConsumerControl::Pause) => consumer.pause(),
+                    Some(ConsumerControl::Resume) => consumer.resume(),
+                    Some(ConsumerControl::Seek(_, reply)) => {
+                        let _ = reply.send(Err(AppError::invalid("An export reads a fixed range, it can not seek")));
+                        Ok(())
+                    }
+                    // Exports are not filtered.
+                    Some(ConsumerControl::ChangeFilter(_)) => Ok(()),
+                }
+                .unwrap_or_else(|err| eprintln!("Export of `{}` could not be paused or resumed: {}", topic, err));
+                continue;
+            },
+            record = consumer.get_next_record() => record,
+        };
+
+        match record {
+            Ok(ConsumedRecord::Message(message)) => {
+                let Some(end) = remaining.get(&message.partition()).copied() else {
+                    continue;
+                };
+                if message.offset() < end {
+                    if let Err(err) = writer.write(&message) {
+                        error = Some(err);
+                        break;
+                    }
+                    progress.written += 1;
+                }
+                if message.offset() + 1 >= end {
+                    remaining.remove(&message.partition());
+                }
+            }
+            // Everything up to the high watermark is read, which is at or past the end offset.
+            Ok(ConsumedRecord::PartitionEof(partition)) => {
+                remaining.remove(&partition);
+            }
+            // Transient errors are retried by librdkafka itself.
+            Err(err) if err.is_retryable() => eprintln!("Export of `{}` failed, retrying: {}", topic, err),
+            Err(err) => {
+                error = Some(err);
+                break;
+            }
+        }
+
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            progress.remaining_partitions = remaining.keys().copied().collect();
+            on_progress(&progress);
+            last_progress = Instant::now();
+        }
+    }
+    progress.remaining_partitions = remaining.keys().copied().collect();
+    on_progress(&progress);
+
+    if let Err(err) = writer.finish() {
+        error.get_or_insert(err);
+    }
+    ExportSummary {
+        path: progress.path,
+        topic: progress.topic,
+        written: progress.written,
+        stopped,
+        error,
+        elapsed_ms: started.elapsed().as_millis(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use rdkafka::{
+        message::{Header, OwnedHeaders, OwnedMessage},
+        Timestamp,
+    };
+
+    use super::{ExportFormat, ExportWriter, BINARY_MAGIC};
+    use crate::kafka::replay::{read_records, ReplayFormat, ReplayOptions};
+
+    fn message(key: Option<&[u8]>, value: Option<&[u8]>, headers: &[(&str, Option<&[u8]>)]) -> OwnedMessage {
+        let headers = (!headers.is_empty()).then(|| {
+            headers.iter().fold(OwnedHeaders::new(), |all, (key, value)| {
+                all.insert(Header {
+                    key,
+                    value: *value,
+                })
+            })
+        });
+        OwnedMessage::new(
+            value.map(<[u8]>::to_vec),
+            key.map(<[u8]>::to_vec),
+            "events".to_owned(),
+            Timestamp::CreateTime(1700000000000),
+            2,
+            41,
+            headers,
+        )
+    }
+
+    /// Writes `messages` to a fresh file and returns its path.
+    fn export(name: &str, format: ExportFormat, messages: &[OwnedMessage]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("kafka-client-export-{}-{}", std::process::id(), name));
+        let mut writer = ExportWriter::create(&path, &format).unwrap();
+        for message in messages {
+            writer.write(message).unwrap();
+        }
+        writer.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn jsonl_exports_replay_as_they_were() {
+        let messages = [
+            message(Some(&[0, 159, 146, 150]), Some(b"{\"id\": 1}\n"), &[("trace", Some(b"t-1")), ("empty", None)]),
+            message(None, Some(b""), &[]),
+            message(Some(b"tombstone"), None, &[]),
+        ];
+        let path = export("roundtrip.jsonl", ExportFormat::Jsonl, &messages);
+        let options = ReplayOptions {
+            preserve_partitions: true,
+            preserve_timestamps: true,
+            ..Default::default()
+        };
+        let records: Vec<_> = read_records(&path, "copy", &ReplayFormat::Jsonl, &options)
+            .unwrap()
+            .map(|(_, record)| record.unwrap())
+            .collect();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(records.len(), 3);
+        let first = &records[0];
+        assert_eq!(first.key.as_deref(), Some(&[0u8, 159, 146, 150][..]), "binary keys survive");
+        assert_eq!(first.value.as_deref(), Some(&b"{\"id\": 1}\n"[..]));
+        assert_eq!(first.headers, [("trace".to_owned(), Some(b"t-1".to_vec())), ("empty".to_owned(), None)]);
+        assert_eq!((first.partition, first.timestamp), (Some(2), Some(1700000000000)));
+        assert_eq!((records[1].key.as_deref(), records[1].value.as_deref()), (None, Some(&b""[..])));
+        assert_eq!(records[2].value, None, "null payloads stay null");
+    }
+
+    #[test]
+    fn csv_quotes_keys_values_and_headers() {
+        let messages = [message(
+            Some(b"a,\"b\""),
+            Some(b"line 1\nline 2"),
+            &[("x,\"y\"", Some(b"v\"1\"")), ("null", None)],
+        )];
+        let path = export("escaping.csv", ExportFormat::Csv, &messages);
+        let mut reader = csv::Reader::from_path(&path).unwrap();
+        let header_row: Vec<String> = reader.headers().unwrap().iter().map(str::to_owned).collect();
+        let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(header_row, ["partition", "offset", "timestamp", "key", "value", "headers"]);
+        assert_eq!(rows.len(), 1, "the newline in the value is quoted");
+        let row = &rows[0];
+        assert_eq!((&row[0], &row[1], &row[2]), ("2", "41", "1700000000000"));
+        assert_eq!((&row[3], &row[4]), ("a,\"b\"", "line 1\nline 2"));
+        let headers: Vec<(String, Option<String>)> = serde_json::from_str(&row[5]).unwrap();
+        assert_eq!(headers, [("x,\"y\"".to_owned(), Some("v\"1\"".to_owned())), ("null".to_owned(), None)]);
+    }
+
+    #[test]
+    fn binary_frames_every_field() {
+        let messages = [message(Some(b"k"), None, &[("h", Some(b"v")), ("n", None)]), message(None, Some(b""), &[])];
+        let path = export("frames.bin", ExportFormat::Binary, &messages);
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let frame = |key: &[u8], value: &[u8], headers: &[u8]| {
+            let mut frame = vec![];
+            frame.extend(2i32.to_be_bytes());
+            frame.extend(41i64.to_be_bytes());
+            frame.extend(1700000000000i64.to_be_bytes());
+            frame.extend(key);
+            frame.extend(value);
+            frame.extend(headers);
+            frame
+        };
+        let null = (-1i32).to_be_bytes();
+        let one = 1i32.to_be_bytes();
+        let headers = [&2i32.to_be_bytes()[..], &one, b"h", &one, b"v", &one, b"n", &null].concat();
+        let first = frame(&[&one[..], b"k"].concat(), &null, &headers);
+        let second = frame(&null, &0i32.to_be_bytes(), &0i32.to_be_bytes());
+        assert_eq!(bytes, [BINARY_MAGIC, &first, &second].concat());
+    }
+}
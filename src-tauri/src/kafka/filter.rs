@@ -0,0 +1,418 @@
+use std::{borrow::Cow, cmp::Ordering};
+
+use jsonpath_rust::{
+    parser::{model::JpQuery, parse_json_path},
+    query::js_path_process,
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::core::error::AppError;
+
+use super::{consumer::MessageEnvelope, deserializer::Decoded, message::MessageBytes};
+
+/// Part of a message a filter looks at.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "content")]
+pub enum FilterTarget {
+    Key,
+    Value,
+    /// Values of the headers with this name, of every header when unset.
+    Header(Option<String>),
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Comparison {
+    /// The path selects anything, `value` is ignored.
+    Exists,
+    Eq,
+    Ne,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    /// A string containing `value`, or an array with an element equal to it.
+    Contains,
+}
+
+/// Inclusive bounds, an unset bound is open.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct Range {
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+}
+impl Range {
+    fn contains(&self, value: i64) -> bool {
+        self.min.is_none_or(|min| value >= min) && self.max.is_none_or(|max| value <= max)
+    }
+}
+
+/// Decides which consumed messages are sent to the frontend. Text filters look at the deserialized key or
+/// value when there is one, and at the raw bytes as UTF-8 otherwise.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "content")]
+pub enum MessageFilter {
+    All(Vec<MessageFilter>),
+    Any(Vec<MessageFilter>),
+    Not(Box<MessageFilter>),
+    Contains {
+        target: FilterTarget,
+        text: String,
+        #[serde(default)]
+        ignore_case: bool,
+    },
+    Regex {
+        target: FilterTarget,
+        pattern: String,
+    },
+    /// Compares what `path` selects in the deserialized key, value or a header holding JSON.
+    /// Matches when any selected value compares true.
+    JsonPath {
+        target: FilterTarget,
+        path: String,
+        comparison: Comparison,
+        value: Option<Value>,
+    },
+    Partition(Range),
+    Offset(Range),
+    /// Milliseconds since the epoch.
+    Timestamp(Range),
+}
+
+impl MessageFilter {
+    /// Parses the patterns and paths once, before any message is read.
+    pub fn compile(&self) -> Result<CompiledFilter, AppError> {
+        Ok(match self {
+            Self::All(filters) => CompiledFilter::All(filters.iter().map(Self::compile).collect::<Result<_, _>>()?),
+            Self::Any(filters) => CompiledFilter::Any(filters.iter().map(Self::compile).collect::<Result<_, _>>()?),
+            Self::Not(filter) => CompiledFilter::Not(Box::new(filter.compile()?)),
+            Self::Contains {
+                target,
+                text,
+                ignore_case,
+            } => CompiledFilter::Contains {
+                target: target.to_owned(),
+                text: if *ignore_case { text.to_lowercase() } else { text.to_owned() },
+                ignore_case: *ignore_case,
+            },
+            Self::Regex { target, pattern } => CompiledFilter::Regex {
+                target: target.to_owned(),
+                regex: Regex::new(pattern)
+                    .map_err(|err| AppError::invalid(format!("Invalid filter pattern '{}': {}", pattern, err)))?,
+            },
+            Self::JsonPath {
+                target,
+                path,
+                comparison,
+                value,
+            } => CompiledFilter::JsonPath {
+                target: target.to_owned(),
+                query: parse_json_path(path)
+                    .map_err(|err| AppError::invalid(format!("Invalid JSONPath '{}': {}", path, err)))?,
+                comparison: *comparison,
+                value: match (comparison, value) {
+                    (Comparison::Exists, _) => Value::Null,
+                    (_, Some(value)) => value.to_owned(),
+                    (_, None) => {
+                        return Err(AppError::invalid(format!(
+                            "JSONPath filter '{}' needs a value to compare with",
+                            path
+                        )))
+                    }
+                },
+            },
+            Self::Partition(range) => CompiledFilter::Partition(*range),
+            Self::Offset(range) => CompiledFilter::Offset(*range),
+            Self::Timestamp(range) => CompiledFilter::Timestamp(*range),
+        })
+    }
+}
+
+pub enum CompiledFilter {
+    All(Vec<CompiledFilter>),
+    Any(Vec<CompiledFilter>),
+    Not(Box<CompiledFilter>),
+    Contains {
+        target: FilterTarget,
+        /// Lower case when ignoring case.
+        text: String,
+        ignore_case: bool,
+    },
+    Regex {
+        target: FilterTarget,
+        regex: Regex,
+    },
+    JsonPath {
+        target: FilterTarget,
+        query: JpQuery,
+        comparison: Comparison,
+        value: Value,
+    },
+    Partition(Range),
+    Offset(Range),
+    Timestamp(Range),
+}
+
+impl CompiledFilter {
+    pub fn matches(&self, message: &MessageEnvelope<Decoded, Decoded>) -> bool {
+        match self {
+            Self::All(filters) => filters.iter().all(|filter| filter.matches(message)),
+            Self::Any(filters) => filters.iter().any(|filter| filter.matches(message)),
+            Self::Not(filter) => !filter.matches(message),
+            Self::Contains {
+                target,
+                text,
+                ignore_case,
+            } => texts(target, message).iter().any(|candidate| match ignore_case {
+                true => candidate.to_lowercase().contains(text.as_str()),
+                false => candidate.contains(text.as_str()),
+            }),
+            Self::Regex { target, regex } => texts(target, message).iter().any(|candidate| regex.is_match(candidate)),
+            Self::JsonPath {
+                target,
+                query,
+                comparison,
+                value,
+            } => documents(target, message).iter().any(|document| {
+                js_path_process(query, document.as_ref())
+                    .map(|selected| selected.into_iter().any(|found| compare(found.val(), *comparison, value)))
+                    .unwrap_or(false)
+            }),
+            Self::Partition(range) => range.contains(message.partition as i64),
+            Self::Offset(range) => range.contains(message.offset),
+            Self::Timestamp(range) => range.contains(message.timestamp),
+        }
+    }
+}
+
+fn headers<'a>(
+    name: &'a Option<String>,
+    message: &'a MessageEnvelope<Decoded, Decoded>,
+) -> impl Iterator<Item = &'a MessageBytes> {
+    message
+        .headers
+        .iter()
+        .filter(move |header| name.as_ref().is_none_or(|name| header.key == *name))
+        .map(|header| &header.value)
+}
+
+fn texts<'a>(target: &'a FilterTarget, message: &'a MessageEnvelope<Decoded, Decoded>) -> Vec<Cow<'a, str>> {
+    let decoded_text = |decoded: &'a Decoded| match &decoded.value {
+        Some(Value::String(text)) => Some(Cow::Borrowed(text.as_str())),
+        Some(value) => Some(Cow::Owned(value.to_string())),
+        None => raw_text(&decoded.bytes),
+    };
+    match target {
+        FilterTarget::Key => decoded_text(&message.key).into_iter().collect(),
+        FilterTarget::Value => decoded_text(&message.payload).into_iter().collect(),
+        FilterTarget::Header(name) => headers(name, message).filter_map(raw_text).collect(),
+    }
+}
+
+fn raw_text(bytes: &MessageBytes) -> Option<Cow<'_, str>> {
+    bytes.raw.as_deref().map(String::from_utf8_lossy)
+}
+
+/// JSON documents of the target, raw bytes are parsed when they were not deserialized.
+fn documents<'a>(target: &'a FilterTarget, message: &'a MessageEnvelope<Decoded, Decoded>) -> Vec<Cow<'a, Value>> {
+    let parse = |bytes: &MessageBytes| bytes.raw.as_deref().and_then(|raw| serde_json::from_slice(raw).ok());
+    let decoded_document = |decoded: &'a Decoded| match &decoded.value {
+        Some(value) => Some(Cow::Borrowed(value)),
+        None => parse(&decoded.bytes).map(Cow::Owned),
+    };
+    match target {
+        FilterTarget::Key => decoded_document(&message.key).into_iter().collect(),
+        FilterTarget::Value => decoded_document(&message.payload).into_iter().collect(),
+        FilterTarget::Header(name) => headers(name, message).filter_map(parse).map(Cow::Owned).collect(),
+    }
+}
+
+fn compare(found: &Value, comparison: Comparison, value: &Value) -> bool {
+    let ordering = || match (found, value) {
+        (Value::Number(found), Value::Number(value)) => found.as_f64()?.partial_cmp(&value.as_f64()?),
+        (Value::String(found), Value::String(value)) => Some(found.cmp(value)),
+        (Value::Bool(found), Value::Bool(value)) => Some(found.cmp(value)),
+        (Value::Null, Value::Null) => Some(Ordering::Equal),
+        _ => None,
+    };
+    match comparison {
+        Comparison::Exists => true,
+        Comparison::Eq => found == value || ordering() == Some(Ordering::Equal),
+        Comparison::Ne => found != value && ordering() != Some(Ordering::Equal),
+        Comparison::Lt => ordering() == Some(Ordering::Less),
+        Comparison::Lte => matches!(ordering(), Some(Ordering::Less | Ordering::Equal)),
+        Comparison::Gt => ordering() == Some(Ordering::Greater),
+        Comparison::Gte => matches!(ordering(), Some(Ordering::Greater | Ordering::Equal)),
+        Comparison::Contains => match (found, value) {
+            (Value::String(found), Value::String(value)) => found.contains(value.as_str()),
+            (Value::Array(items), value) => items.iter().any(|item| compare(item, Comparison::Eq, value)),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::{Comparison, FilterTarget, MessageFilter, Range};
+    use crate::kafka::{
+        consumer::MessageEnvelope,
+        deserializer::Decoded,
+        message::{DisplayFormat, MessageBytes, RecordHeader},
+    };
+
+    fn decoded(raw: &str, value: Option<Value>) -> Decoded {
+        Decoded {
+            bytes: MessageBytes::new(Some(raw.as_bytes()), DisplayFormat::Utf8),
+            value,
+            error: None,
+        }
+    }
+
+    /// A JSON order value, deserialized, with a raw text key and two `trace` headers.
+    fn message() -> MessageEnvelope<Decoded, Decoded> {
+        let value = json!({"id": 42, "total": 9.5, "code": "10", "tags": ["new", "gift"], "customer": "Ada"});
+        MessageEnvelope {
+            key: decoded("Order-42", None),
+            partition: 3,
+            offset: 100,
+            headers: vec![
+                RecordHeader {
+                    key: "trace".to_owned(),
+                    value: MessageBytes::new(Some(b"first"), DisplayFormat::Utf8),
+                },
+                RecordHeader {
+                    key: "meta".to_owned(),
+                    value: MessageBytes::new(Some(br#"{"retries": 2}"#), DisplayFormat::Utf8),
+                },
+                RecordHeader {
+                    key: "trace".to_owned(),
+                    value: MessageBytes::new(Some(b"second"), DisplayFormat::Utf8),
+                },
+            ],
+            payload: decoded(&value.to_string(), Some(value)),
+            timestamp: 1_700_000_000_000,
+        }
+    }
+
+    fn matches(filter: MessageFilter) -> bool {
+        filter.compile().unwrap().matches(&message())
+    }
+
+    fn contains(target: FilterTarget, text: &str, ignore_case: bool) -> MessageFilter {
+        MessageFilter::Contains {
+            target,
+            text: text.to_owned(),
+            ignore_case,
+        }
+    }
+
+    fn json_path(path: &str, comparison: Comparison, value: Value) -> MessageFilter {
+        MessageFilter::JsonPath {
+            target: FilterTarget::Value,
+            path: path.to_owned(),
+            comparison,
+            value: Some(value),
+        }
+    }
+
+    #[test]
+    fn contains_respects_case() {
+        assert!(matches(contains(FilterTarget::Key, "Order", false)));
+        assert!(!matches(contains(FilterTarget::Key, "order", false)));
+        assert!(matches(contains(FilterTarget::Key, "ORDER-42", true)));
+        // Deserialized values are matched in their JSON form.
+        assert!(matches(contains(FilterTarget::Value, "\"customer\":\"ada\"", true)));
+        assert!(!matches(contains(FilterTarget::Value, "\"customer\":\"ada\"", false)));
+    }
+
+    #[test]
+    fn headers_are_matched_by_name_with_repeated_keys() {
+        let header = |name: &str| FilterTarget::Header(Some(name.to_owned()));
+        assert!(matches(contains(header("trace"), "first", false)));
+        assert!(matches(contains(header("trace"), "second", false)));
+        assert!(!matches(contains(header("trace"), "retries", false)));
+        assert!(matches(contains(FilterTarget::Header(None), "retries", false)));
+        assert!(!matches(contains(header("missing"), "first", false)));
+
+        assert!(matches(MessageFilter::JsonPath {
+            target: header("meta"),
+            path: "$.retries".to_owned(),
+            comparison: Comparison::Gte,
+            value: Some(json!(2)),
+        }));
+    }
+
+    #[test]
+    fn json_path_orders_numbers_and_strings_apart() {
+        assert!(matches(json_path("$.id", Comparison::Gt, json!(9))));
+        assert!(matches(json_path("$.total", Comparison::Lt, json!(10))));
+        assert!(matches(json_path("$.id", Comparison::Eq, json!(42.0))));
+        // Strings compare as text, "10" sorts before "9".
+        assert!(matches(json_path("$.code", Comparison::Lt, json!("9"))));
+        // A number never orders against a string.
+        assert!(!matches(json_path("$.code", Comparison::Lt, json!(9))));
+        assert!(!matches(json_path("$.code", Comparison::Gte, json!(9))));
+    }
+
+    #[test]
+    fn json_path_ne_and_exists() {
+        assert!(matches(json_path("$.id", Comparison::Ne, json!(41))));
+        assert!(!matches(json_path("$.id", Comparison::Ne, json!(42.0))));
+        assert!(matches(json_path("$.code", Comparison::Ne, json!(10))));
+        assert!(!matches(json_path("$.missing", Comparison::Ne, json!(1))), "nothing selected never matches");
+
+        let exists = |path: &str| MessageFilter::JsonPath {
+            target: FilterTarget::Value,
+            path: path.to_owned(),
+            comparison: Comparison::Exists,
+            value: None,
+        };
+        assert!(matches(exists("$.tags")));
+        assert!(!matches(exists("$.missing")));
+    }
+
+    #[test]
+    fn json_path_contains_on_arrays_and_strings() {
+        assert!(matches(json_path("$.tags", Comparison::Contains, json!("gift"))));
+        assert!(!matches(json_path("$.tags", Comparison::Contains, json!("gi"))), "array elements must be equal");
+        assert!(matches(json_path("$.customer", Comparison::Contains, json!("d"))));
+        assert!(!matches(json_path("$.id", Comparison::Contains, json!(4))));
+    }
+
+    #[test]
+    fn combinators_and_ranges() {
+        let in_partition = |min, max| MessageFilter::Partition(Range { min, max });
+        assert!(matches(in_partition(Some(3), Some(3))));
+        assert!(!matches(in_partition(None, Some(2))));
+        assert!(matches(MessageFilter::All(vec![
+            in_partition(Some(0), None),
+            MessageFilter::Offset(Range { min: Some(100), max: None }),
+            MessageFilter::Not(Box::new(MessageFilter::Timestamp(Range { min: None, max: Some(0) }))),
+        ])));
+        assert!(matches(MessageFilter::Any(vec![
+            in_partition(None, Some(0)),
+            contains(FilterTarget::Key, "42", false),
+        ])));
+        assert!(!matches(MessageFilter::Any(vec![])));
+    }
+
+    #[test]
+    fn invalid_filters_fail_to_compile() {
+        let regex = MessageFilter::Regex {
+            target: FilterTarget::Key,
+            pattern: "(".to_owned(),
+        };
+        assert!(regex.compile().is_err());
+        assert!(json_path("$[", Comparison::Eq, json!(1)).compile().is_err());
+        let without_value = MessageFilter::JsonPath {
+            target: FilterTarget::Value,
+            path: "$.id".to_owned(),
+            comparison: Comparison::Eq,
+            value: None,
+        };
+        assert!(without_value.compile().is_err());
+    }
+}
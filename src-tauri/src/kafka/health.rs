@@ -0,0 +1,226 @@
+use std::{
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use rdkafka::{
+    admin::AdminClient, bindings::rd_kafka_controllerid, client::ClientContext,
+    config::FromClientConfigAndContext, error::KafkaError, types::RDKafkaErrorCode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::core::{config::ClusterConfig, error::AppError, secrets::SecretStore};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const METADATA_TIMEOUT: Duration = Duration::from_secs(10);
+/// Port librdkafka connects to when a bootstrap server has none.
+const DEFAULT_PORT: u16 = 9092;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerCheck {
+    pub address: String,
+    pub resolved: Vec<String>,
+    pub reachable: bool,
+    pub connect_ms: Option<u128>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", content = "content")]
+pub enum AuthenticationStatus {
+    /// The cluster does not use SASL or client certificates.
+    NotRequired,
+    Succeeded,
+    Failed(String),
+    /// No broker could be reached to attempt authentication.
+    Unknown,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionTestReport {
+    pub servers: Vec<ServerCheck>,
+    pub authentication: AuthenticationStatus,
+    pub metadata_fetch_ms: Option<u128>,
+    pub broker_count: Option<usize>,
+    pub controller_id: Option<i32>,
+    pub error: Option<AppError>,
+}
+
+/// Collects the global errors librdkafka reports while the test client is connecting,
+/// authentication failures only show up there.
+#[derive(Default)]
+struct ConnectionTestContext {
+    errors: Mutex<Vec<(Option<RDKafkaErrorCode>, String)>>,
+}
+impl ClientContext for ConnectionTestContext {
+    fn error(&self, error: KafkaError, reason: &str) {
+        if let Ok(mut errors) = self.errors.lock() {
+            errors.push((error.rdkafka_error_code(), reason.to_owned()));
+        }
+    }
+}
+impl ConnectionTestContext {
+    fn authentication_error(&self) -> Option<String> {
+        self.errors.lock().ok().and_then(|errors| {
+            errors
+                .iter()
+                .find(|(code, _)| {
+                    matches!(
+                        code,
+                        Some(RDKafkaErrorCode::Authentication | RDKafkaErrorCode::SaslAuthenticationFailed | RDKafkaErrorCode::SSL)
+                    )
+                })
+                .map(|(_, reason)| reason.to_owned())
+        })
+    }
+    fn last_error(&self) -> Option<String> {
+        self.errors
+            .lock()
+            .ok()
+            .and_then(|errors| errors.last().map(|(_, reason)| reason.to_owned()))
+    }
+}
+
+/// Checks every bootstrap server, then connects a client to measure a metadata fetch.
+/// Failures are part of the report, the test itself never fails.
+pub fn test_connection(cluster: &ClusterConfig, secrets: &SecretStore) -> ConnectionTestReport {
+    let servers: Vec<ServerCheck> = cluster
+        .bootstrap_servers
+        .iter()
+        .map(|server| check_server(server))
+        .collect();
+    let any_reachable = servers.iter().any(|server| server.reachable);
+    let security_required = cluster.security.protocol.uses_sasl()
+        || cluster.security.ssl.as_ref().is_some_and(|ssl| ssl.certificate.is_some());
+
+    let mut report = ConnectionTestReport {
+        servers,
+        authentication: AuthenticationStatus::Unknown,
+        metadata_fetch_ms: None,
+        broker_count: None,
+        controller_id: None,
+        error: None,
+    };
+    if !any_reachable {
+        report.error = Some(AppError::from_code(
+            RDKafkaErrorCode::AllBrokersDown,
+            "None of the bootstrap servers accepted a TCP connection",
+        ));
+        return report;
+    }
+
+    let client_config = match cluster.client_config(secrets) {
+        Ok(config) => config,
+        Err(err) => {
+            report.error = Some(err);
+            return report;
+        }
+    };
+    let admin = match AdminClient::from_config_and_context(&client_config, ConnectionTestContext::default()) {
+        Ok(admin) => admin,
+        Err(err) => {
+            report.error = Some(AppError::from(err).context("Could not create client"));
+            return report;
+        }
+    };
+
+    let started = Instant::now();
+    match admin.inner().fetch_metadata(None, METADATA_TIMEOUT) {
+        Ok(metadata) => {
+            report.metadata_fetch_ms = Some(started.elapsed().as_millis());
+            report.broker_count = Some(metadata.brokers().len());
+            let controller_id = unsafe { rd_kafka_controllerid(admin.inner().native_ptr(), 1000) };
+            report.controller_id = Some(controller_id).filter(|id| *id >= 0);
+            report.authentication = if security_required {
+                AuthenticationStatus::Succeeded
+            } else {
+                AuthenticationStatus::NotRequired
+            };
+        }
+        Err(err) => {
+            let context = admin.inner().context();
+            report.authentication = match context.authentication_error() {
+                Some(reason) => AuthenticationStatus::Failed(reason),
+                None if !security_required => AuthenticationStatus::NotRequired,
+                None => AuthenticationStatus::Unknown,
+            };
+            let err = AppError::from(err);
+            report.error = Some(match context.last_error() {
+                Some(reason) => err.context(format!("Metadata fetch failed ({})", reason)),
+                None => err.context("Metadata fetch failed"),
+            });
+        }
+    }
+
+    report
+}
+
+fn check_server(server: &str) -> ServerCheck {
+    let mut check = ServerCheck {
+        address: server.to_owned(),
+        resolved: vec![],
+        reachable: false,
+        connect_ms: None,
+        error: None,
+    };
+
+    let address = socket_address(server);
+    let addresses: Vec<SocketAddr> = match address.to_socket_addrs() {
+        Ok(addresses) => addresses.collect(),
+        Err(err) => {
+            check.error = Some(format!("Could not resolve '{}': {}", address, err));
+            return check;
+        }
+    };
+    check.resolved = addresses.iter().map(|addr| addr.to_string()).collect();
+
+    let mut errors = vec![];
+    for address in addresses {
+        let started = Instant::now();
+        match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+            Ok(_) => {
+                check.reachable = true;
+                check.connect_ms = Some(started.elapsed().as_millis());
+                return check;
+            }
+            Err(err) => errors.push(format!("{}: {}", address, err)),
+        }
+    }
+    check.error = Some(errors.join(", "));
+    check
+}
+
+/// `host:port` of a bootstrap server, read like librdkafka does: without a `PROTOCOL://` prefix
+/// and on port 9092 when none is given.
+fn socket_address(server: &str) -> String {
+    let address = server.trim();
+    let address = address.split_once("://").map_or(address, |(_protocol, address)| address);
+    match address.strip_prefix('[') {
+        Some(ipv6) if ipv6.contains("]:") => address.to_owned(),
+        Some(_) => format!("{}:{}", address, DEFAULT_PORT),
+        // More than one colon is a bare IPv6 address, it can not carry a port without brackets.
+        None if address.matches(':').count() > 1 => format!("[{}]:{}", address, DEFAULT_PORT),
+        None if address.contains(':') => address.to_owned(),
+        None => format!("{}:{}", address, DEFAULT_PORT),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::socket_address;
+
+    #[test]
+    fn bootstrap_servers_resolve_like_librdkafka() {
+        assert_eq!(socket_address("broker1:9093"), "broker1:9093");
+        assert_eq!(socket_address("broker1"), "broker1:9092");
+        assert_eq!(socket_address("SASL_SSL://broker1:9093"), "broker1:9093");
+        assert_eq!(socket_address("PLAINTEXT://broker1"), "broker1:9092");
+        assert_eq!(socket_address("[::1]"), "[::1]:9092");
+        assert_eq!(socket_address("[::1]:9094"), "[::1]:9094");
+        assert_eq!(socket_address("::1"), "[::1]:9092");
+        assert_eq!(socket_address("SSL://fe80::1"), "[fe80::1]:9092");
+    }
+}
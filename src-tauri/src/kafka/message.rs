@@ -0,0 +1,190 @@
+use std::fmt::Write;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+/// How the bytes of a key, payload or header value look, detected on receipt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Encoding {
+    Null,
+    /// Valid UTF-8 without control characters other than whitespace.
+    Utf8,
+    Binary,
+}
+
+/// How bytes are rendered for display, the raw bytes are always kept alongside.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayFormat {
+    /// Invalid sequences are replaced with U+FFFD.
+    #[default]
+    Utf8,
+    Hex,
+    Base64,
+    /// Offset, hex and printable characters, 16 bytes a line like `hexdump -C`.
+    Hexdump,
+}
+
+/// Key, payload or header value of a message. Serialized with the raw bytes as base64, `null` when absent,
+/// and deserialized from that shape or from a plain string taken as UTF-8.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MessageBytes {
+    #[serde(serialize_with = "serialize_raw")]
+    pub raw: Option<Vec<u8>>,
+    pub encoding: Encoding,
+    /// The raw bytes in the requested display format.
+    pub text: Option<String>,
+}
+
+impl MessageBytes {
+    pub fn new(raw: Option<&[u8]>, display: DisplayFormat) -> Self {
+        Self {
+            raw: raw.map(<[u8]>::to_vec),
+            encoding: raw.map_or(Encoding::Null, detect_encoding),
+            text: raw.map(|raw| render(raw, display)),
+        }
+    }
+
+    pub fn render(&self, display: DisplayFormat) -> Option<String> {
+        self.raw.as_deref().map(|raw| render(raw, display))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordHeader {
+    pub key: String,
+    pub value: MessageBytes,
+}
+
+pub fn detect_encoding(bytes: &[u8]) -> Encoding {
+    match std::str::from_utf8(bytes) {
+        Ok(text) if !text.chars().any(|c| c.is_control() && !c.is_whitespace()) => Encoding::Utf8,
+        _ => Encoding::Binary,
+    }
+}
+
+pub fn render(bytes: &[u8], display: DisplayFormat) -> String {
+    match display {
+        DisplayFormat::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+        DisplayFormat::Hex => bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        }),
+        DisplayFormat::Base64 => BASE64.encode(bytes),
+        DisplayFormat::Hexdump => hexdump(bytes),
+    }
+}
+
+fn hexdump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let _ = write!(dump, "{:08x} ", line * 16);
+        for column in 0..16 {
+            if column == 8 {
+                dump.push(' ');
+            }
+            match chunk.get(column) {
+                Some(byte) => {
+                    let _ = write!(dump, " {:02x}", byte);
+                }
+                None => dump.push_str("   "),
+            }
+        }
+        let printable: String = chunk
+            .iter()
+            .map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' })
+            .collect();
+        let _ = writeln!(dump, "  |{}|", printable);
+    }
+    let _ = write!(dump, "{:08x}", bytes.len());
+    dump
+}
+
+fn serialize_raw<S: Serializer>(raw: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+    match raw {
+        Some(raw) => serializer.serialize_str(&BASE64.encode(raw)),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MessageBytesRepr {
+    Text(String),
+    Raw { raw: Option<String> },
+}
+
+impl<'de> Deserialize<'de> for MessageBytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = match Option::<MessageBytesRepr>::deserialize(deserializer)? {
+            None => None,
+            Some(MessageBytesRepr::Text(text)) => Some(text.into_bytes()),
+            Some(MessageBytesRepr::Raw { raw }) => raw
+                .map(|raw| BASE64.decode(raw))
+                .transpose()
+                .map_err(|err| D::Error::custom(format!("raw is not base64: {}", err)))?,
+        };
+        Ok(Self::new(raw.as_deref(), DisplayFormat::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{render, DisplayFormat, Encoding, MessageBytes};
+
+    #[test]
+    fn null_and_empty_are_told_apart() {
+        let null = MessageBytes::new(None, DisplayFormat::Utf8);
+        assert_eq!((null.raw, null.encoding, null.text), (None, Encoding::Null, None));
+
+        let empty = MessageBytes::new(Some(&[]), DisplayFormat::Utf8);
+        assert_eq!((empty.raw, empty.encoding, empty.text), (Some(vec![]), Encoding::Utf8, Some(String::new())));
+    }
+
+    #[test]
+    fn binary_bytes_are_detected_and_rendered() {
+        let text = MessageBytes::new(Some("tab\tand newline\n".as_bytes()), DisplayFormat::Utf8);
+        assert_eq!(text.encoding, Encoding::Utf8, "whitespace is text");
+
+        let binary = MessageBytes::new(Some(&[0x00, 0xff, 0xfe, 0x01]), DisplayFormat::Hex);
+        assert_eq!(binary.encoding, Encoding::Binary);
+        assert_eq!(binary.text.as_deref(), Some("00fffe01"));
+        assert_eq!(binary.render(DisplayFormat::Base64).as_deref(), Some("AP/+AQ=="));
+        assert_eq!(binary.render(DisplayFormat::Utf8).as_deref(), Some("\0\u{fffd}\u{fffd}\u{1}"));
+        assert_eq!(MessageBytes::new(Some(&[0x07]), DisplayFormat::Utf8).encoding, Encoding::Binary);
+    }
+
+    #[test]
+    fn hexdump_pads_the_last_line() {
+        let dump = render(b"0123456789abcdef\x00YZ", DisplayFormat::Hexdump);
+        let expected = [
+            "00000000  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  |0123456789abcdef|\n".to_owned(),
+            format!("00000010  00 59 5a{}  |.YZ|\n", " ".repeat(13 * 3 + 1)),
+            "00000013".to_owned(),
+        ]
+        .concat();
+        assert_eq!(dump, expected);
+        assert_eq!(render(&[], DisplayFormat::Hexdump), "00000000");
+    }
+
+    #[test]
+    fn bytes_serialize_as_base64_and_read_back() {
+        for raw in [None, Some(&[][..]), Some(&b"text"[..]), Some(&[0xff, 0x00][..])] {
+            let bytes = MessageBytes::new(raw, DisplayFormat::Utf8);
+            let serialized = serde_json::to_value(&bytes).unwrap();
+            assert_eq!(serde_json::from_value::<MessageBytes>(serialized).unwrap(), bytes);
+        }
+
+        let serialized = serde_json::to_value(MessageBytes::new(Some(b"hi"), DisplayFormat::Hex)).unwrap();
+        assert_eq!(serialized, json!({"raw": "aGk=", "encoding": "Utf8", "text": "6869"}));
+
+        // Plain strings are taken as UTF-8, `raw` has to be base64.
+        let text: MessageBytes = serde_json::from_value(json!("hi")).unwrap();
+        assert_eq!(text.raw.as_deref(), Some(&b"hi"[..]));
+        let null: MessageBytes = serde_json::from_value(json!({"raw": null})).unwrap();
+        assert_eq!(null.encoding, Encoding::Null);
+        assert!(serde_json::from_value::<MessageBytes>(json!({"raw": "not base64!"})).is_err());
+    }
+}
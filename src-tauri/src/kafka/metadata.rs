@@ -1,6 +1,16 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
 use rdkafka::metadata::{Metadata, MetadataBroker, MetadataPartition, MetadataTopic};
 use serde::{Deserialize, Serialize};
 
+use crate::core::error::AppError;
+
+use super::schema_registry::TopicSubjects;
+
 #[derive(Serialize, Deserialize, Clone)]
 
 pub struct ClusterMetadata {
@@ -18,7 +28,7 @@ impl ClusterMetadata {
         }
     }
 }
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct Broker {
     pub id: i32,
     pub host: String,
@@ -34,10 +44,13 @@ impl Broker {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct Topic {
     pub name: String,
     pub partitions: Vec<Partition>,
+    /// Registry subjects of the keys and values, linked when the topic list is requested.
+    #[serde(default)]
+    pub subjects: TopicSubjects,
 }
 impl Topic {
     pub fn from(topic: &MetadataTopic) -> Self {
@@ -48,10 +61,11 @@ impl Topic {
                 .into_iter()
                 .map(Partition::from)
                 .collect(),
+            subjects: TopicSubjects::default(),
         }
     }
 }
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct Partition {
     pub id: i32,
     pub isr: Vec<i32>,
@@ -68,3 +82,208 @@ impl Partition {
         }
     }
 }
+
+/// Difference between two metadata fetches of a cluster, emitted so views can
+/// update the changed topics only.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataChanges {
+    pub cluster: String,
+    pub added_topics: Vec<Topic>,
+    pub removed_topics: Vec<String>,
+    /// Topics whose partitions, leaders, replicas or ISR changed.
+    pub changed_topics: Vec<Topic>,
+    /// The new broker list, only set when brokers joined or left.
+    pub brokers: Option<Vec<Broker>>,
+}
+impl MetadataChanges {
+    pub fn between(cluster: &str, previous: &ClusterMetadata, current: &ClusterMetadata) -> Self {
+        let previous_topics: HashMap<&str, &Topic> = previous.topics.iter().map(|t| (t.name.as_str(), t)).collect();
+        let current_names: Vec<&str> = current.topics.iter().map(|t| t.name.as_str()).collect();
+
+        let mut added_topics = vec![];
+        let mut changed_topics = vec![];
+        for topic in current.topics.iter() {
+            match previous_topics.get(topic.name.as_str()) {
+                None => added_topics.push(topic.to_owned()),
+                Some(previous) if *previous != topic => changed_topics.push(topic.to_owned()),
+                Some(_) => {}
+            }
+        }
+        let removed_topics = previous
+            .topics
+            .iter()
+            .filter(|topic| !current_names.contains(&topic.name.as_str()))
+            .map(|topic| topic.name.to_owned())
+            .collect();
+
+        Self {
+            cluster: cluster.to_owned(),
+            added_topics,
+            removed_topics,
+            changed_topics,
+            brokers: Some(current.brokers.to_owned()).filter(|brokers| *brokers != previous.brokers),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added_topics.is_empty()
+            && self.removed_topics.is_empty()
+            && self.changed_topics.is_empty()
+            && self.brokers.is_none()
+    }
+}
+
+struct CachedMetadata {
+    metadata: ClusterMetadata,
+    /// `None` once invalidated, the metadata is kept to diff the next fetch against.
+    fetched_at: Option<Instant>,
+}
+
+/// Last fetched metadata per cluster name.
+#[derive(Default)]
+pub struct MetadataCache {
+    entries: Mutex<HashMap<String, CachedMetadata>>,
+}
+impl MetadataCache {
+    /// The cached metadata of `cluster` if it was fetched less than `ttl` ago.
+    pub fn get_fresh(&self, cluster: &str, ttl: Duration) -> Option<ClusterMetadata> {
+        let entries = self.entries.lock().ok()?;
+        entries
+            .get(cluster)
+            .filter(|entry| entry.fetched_at.is_some_and(|at| at.elapsed() < ttl))
+            .map(|entry| entry.metadata.to_owned())
+    }
+
+    /// Stores freshly fetched metadata, returning what changed since the previous fetch.
+    pub fn update(&self, cluster: &str, metadata: ClusterMetadata) -> Result<Option<MetadataChanges>, AppError> {
+        let mut entries = self.entries.lock()?;
+        let changes = entries
+            .get(cluster)
+            .map(|previous| MetadataChanges::between(cluster, &previous.metadata, &metadata))
+            .filter(|changes| !changes.is_empty());
+
+        entries.insert(
+            cluster.to_owned(),
+            CachedMetadata {
+                metadata,
+                fetched_at: Some(Instant::now()),
+            },
+        );
+        Ok(changes)
+    }
+
+    /// Forces the next read of `cluster` to refetch, e.g. after creating or deleting a topic.
+    pub fn invalidate(&self, cluster: &str) {
+        if let Ok(mut entries) = self.entries.lock() {
+            if let Some(entry) = entries.get_mut(cluster) {
+                entry.fetched_at = None;
+            }
+        }
+    }
+
+    pub fn remove(&self, cluster: &str) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.remove(cluster);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Broker, ClusterMetadata, MetadataCache, MetadataChanges, Partition, Topic};
+
+    fn topic(name: &str, partitions: i32) -> Topic {
+        Topic {
+            name: name.to_owned(),
+            partitions: (0..partitions)
+                .map(|id| Partition {
+                    id,
+                    isr: vec![1],
+                    replicas: vec![1],
+                    leader: 1,
+                })
+                .collect(),
+            subjects: Default::default(),
+        }
+    }
+
+    fn cluster(brokers: &[i32], topics: Vec<Topic>) -> ClusterMetadata {
+        ClusterMetadata {
+            originating_broker_id: 1,
+            brokers: brokers
+                .iter()
+                .map(|id| Broker {
+                    id: *id,
+                    host: format!("broker-{}", id),
+                    port: 9092,
+                })
+                .collect(),
+            topics,
+        }
+    }
+
+    fn names(topics: &[Topic]) -> Vec<&str> {
+        topics.iter().map(|topic| topic.name.as_str()).collect()
+    }
+
+    #[test]
+    fn changes_list_added_removed_and_changed_topics() {
+        let previous = cluster(&[1], vec![topic("kept", 1), topic("grown", 1), topic("deleted", 3)]);
+        let current = cluster(&[1], vec![topic("grown", 4), topic("kept", 1), topic("created", 2)]);
+
+        let changes = MetadataChanges::between("local", &previous, &current);
+        assert_eq!(changes.cluster, "local");
+        assert_eq!(names(&changes.added_topics), ["created"]);
+        assert_eq!(changes.removed_topics, ["deleted"]);
+        assert_eq!(names(&changes.changed_topics), ["grown"]);
+        assert_eq!(changes.changed_topics[0].partitions.len(), 4);
+        assert!(changes.brokers.is_none(), "the brokers did not change");
+
+        let mut moved = topic("kept", 1);
+        moved.partitions[0].leader = 2;
+        let changes = MetadataChanges::between("local", &previous, &cluster(&[1, 2], vec![moved]));
+        assert_eq!(names(&changes.changed_topics), ["kept"], "a new leader is a change");
+        assert_eq!(changes.removed_topics, ["grown", "deleted"]);
+        assert_eq!(changes.brokers.map(|brokers| brokers.len()), Some(2));
+
+        assert!(MetadataChanges::between("local", &previous, &previous).is_empty());
+    }
+
+    #[test]
+    fn cache_reports_changes_since_the_previous_fetch() {
+        let cache = MetadataCache::default();
+        let first = cluster(&[1], vec![topic("events", 1)]);
+        assert!(cache.update("local", first.clone()).unwrap().is_none(), "nothing to compare the first fetch to");
+        assert!(cache.update("local", first.clone()).unwrap().is_none(), "unchanged");
+        assert!(cache.update("other", cluster(&[1], vec![])).unwrap().is_none(), "clusters are kept apart");
+
+        cache.invalidate("local");
+        let changes = cache.update("local", cluster(&[1], vec![])).unwrap().unwrap();
+        assert_eq!(changes.removed_topics, ["events"], "invalidating keeps the metadata to compare to");
+
+        cache.remove("local");
+        assert!(cache.update("local", first).unwrap().is_none());
+    }
+
+    #[test]
+    fn cached_metadata_expires_after_the_ttl() {
+        let cache = MetadataCache::default();
+        let ttl = Duration::from_secs(30);
+        assert!(cache.get_fresh("local", ttl).is_none());
+
+        cache.update("local", cluster(&[1], vec![topic("events", 1)])).unwrap();
+        let fresh = cache.get_fresh("local", ttl).unwrap();
+        assert_eq!(names(&fresh.topics), ["events"]);
+        assert!(cache.get_fresh("local", Duration::ZERO).is_none());
+
+        cache.entries.lock().unwrap().get_mut("local").unwrap().fetched_at = Some(Instant::now() - ttl);
+        assert!(cache.get_fresh("local", ttl).is_none(), "fetched exactly a TTL ago");
+        assert!(cache.get_fresh("local", ttl * 2).is_some());
+
+        cache.invalidate("local");
+        assert!(cache.get_fresh("local", ttl * 2).is_none());
+    }
+}
@@ -1,4 +1,17 @@
 pub mod admin;
 pub mod consumer;
+pub mod deserializer;
+pub mod export;
+pub mod filter;
+pub mod health;
+pub mod message;
 pub mod metadata;
+pub mod pool;
+pub mod producer;
+pub mod properties;
+pub mod replay;
+pub mod schema_registry;
+pub mod security;
+pub mod serializer;
+pub mod session;
 pub mod util;
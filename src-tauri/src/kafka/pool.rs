@@ -0,0 +1,132 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use rdkafka::{admin::AdminClient, client::DefaultClientContext, ClientConfig};
+
+use crate::core::{config::ClusterConfig, error::AppError, secrets::SecretStore};
+
+use super::{
+    admin::create_admin_client,
+    consumer::KafkaConsumer,
+    producer::{KafkaProducer, ProducerSettings},
+    schema_registry::SchemaRegistryClient,
+};
+
+/// Long-lived clients shared by every command against one cluster.
+pub struct ClusterClients {
+    pub admin: Arc<AdminClient<DefaultClientContext>>,
+    /// Consumer without assignments, used for metadata and group listings.
+    pub consumer: KafkaConsumer,
+    /// Producers by the settings they were created with, created on first send.
+    producers: Mutex<HashMap<ProducerSettings, Arc<KafkaProducer>>>,
+    /// Client of the cluster's schema registry, created on first use so its schema cache is shared.
+    registry: Mutex<Option<Arc<SchemaRegistryClient>>>,
+    /// Serialized cluster config the clients were built from.
+    fingerprint: String,
+}
+
+/// Clients per cluster name, created on first use and reused until the cluster changes.
+#[derive(Default)]
+pub struct ClientPool {
+    clients: Mutex<HashMap<String, Arc<ClusterClients>>>,
+}
+
+impl ClientPool {
+    pub fn get(&self, cluster: &ClusterConfig, secrets: &SecretStore) -> Result<Arc<ClusterClients>, AppError> {
+        // Remembered topic formats do not affect the clients, changing them should not recreate those.
+        let fingerprint = serde_json::to_string(&ClusterConfig {
+            topic_formats: HashMap::new(),
+            ..cluster.to_owned()
+        })
+        .map_err(|err| AppError::internal(err.to_string()))?;
+        let mut clients = self.clients.lock()?;
+        if let Some(existing) = clients.get(&cluster.name).filter(|c| c.fingerprint == fingerprint) {
+            return Ok(existing.clone());
+        }
+
+        let created = Arc::new(ClusterClients {
+            admin: Arc::new(create_admin_client(cluster, secrets, ClientConfig::default())?),
+            consumer: KafkaConsumer::connect(cluster, secrets)?,
+            producers: Mutex::new(HashMap::new()),
+            registry: Mutex::new(None),
+            fingerprint,
+        });
+        clients.insert(cluster.name.to_owned(), created.clone());
+        Ok(created)
+    }
+
+    /// The pooled admin client, or a dedicated one when per-command `overrides` are given.
+    pub fn admin(
+        &self,
+        cluster: &ClusterConfig,
+        secrets: &SecretStore,
+        overrides: Option<HashMap<String, String>>,
+    ) -> Result<Arc<AdminClient<DefaultClientContext>>, AppError> {
+        match overrides.filter(|o| !o.is_empty()) {
+            Some(overrides) => {
+                let cluster = cluster.with_overrides(Some(overrides))?;
+                create_admin_client(&cluster, secrets, ClientConfig::default()).map(Arc::new)
+            }
+            None => self.get(cluster, secrets).map(|clients| clients.admin.clone()),
+        }
+    }
+
+    /// The pooled producer for `settings`, or a dedicated one when per-command `overrides` are given.
+    pub fn producer(
+        &self,
+        cluster: &ClusterConfig,
+        secrets: &SecretStore,
+        settings: ProducerSettings,
+        overrides: Option<HashMap<String, String>>,
+    ) -> Result<Arc<KafkaProducer>, AppError> {
+        if let Some(overrides) = overrides.filter(|o| !o.is_empty()) {
+            let cluster = cluster.with_overrides(Some(overrides))?;
+            return KafkaProducer::connect(&cluster, secrets, settings).map(Arc::new);
+        }
+
+        let clients = self.get(cluster, secrets)?;
+        let mut producers = clients.producers.lock()?;
+        if let Some(existing) = producers.get(&settings) {
+            return Ok(existing.clone());
+        }
+        let created = Arc::new(KafkaProducer::connect(cluster, secrets, settings)?);
+        producers.insert(settings, created.clone());
+        Ok(created)
+    }
+
+    /// The pooled schema registry client, `None` when the cluster has no registry configured.
+    pub fn schema_registry(
+        &self,
+        cluster: &ClusterConfig,
+        secrets: &SecretStore,
+    ) -> Result<Option<Arc<SchemaRegistryClient>>, AppError> {
+        let Some(config) = &cluster.schema_registry else {
+            return Ok(None);
+        };
+        let clients = self.get(cluster, secrets)?;
+        let mut registry = clients.registry.lock()?;
+        if let Some(existing) = registry.as_ref() {
+            return Ok(Some(existing.clone()));
+        }
+        let created = Arc::new(
+            SchemaRegistryClient::new(config, secrets).map_err(|err| err.context(format!("Cluster '{}'", cluster.name)))?,
+        );
+        *registry = Some(created.clone());
+        Ok(Some(created))
+    }
+
+    /// Drops the clients of `cluster`, they are closed once no command uses them anymore.
+    pub fn remove(&self, cluster: &str) {
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.remove(cluster);
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.clear();
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use rdkafka::{
+    error::{KafkaError, RDKafkaErrorCode},
+    message::{Header, OwnedHeaders},
+    producer::{FutureProducer, FutureRecord},
+};
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+
+use crate::core::{
+    config::ClusterConfig,
+    error::{AppError, Resource},
+    secrets::SecretStore,
+};
+
+use super::{admin::set_default, schema_registry::SchemaTarget};
+
+/// How long a send may wait for room in the local producer queue.
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+const QUEUE_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Acks {
+    /// Fire and forget, the offset of the delivery is unknown.
+    None,
+    Leader,
+    All,
+}
+impl Acks {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::None => "0",
+            Self::Leader => "1",
+            Self::All => "all",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    None,
+    Gzip,
+    Snappy,
+    Lz4,
+    Zstd,
+}
+impl Compression {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Gzip => "gzip",
+            Self::Snappy => "snappy",
+            Self::Lz4 => "lz4",
+            Self::Zstd => "zstd",
+        }
+    }
+}
+
+/// Producer level settings a send can ask for, unset ones keep the cluster defaults.
+/// Each distinct combination gets its own producer since librdkafka can not change them per message.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ProducerSettings {
+    pub acks: Option<Acks>,
+    pub compression: Option<Compression>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MessageHeader {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProduceRequest {
+    pub topic: String,
+    pub key: Option<String>,
+    /// `None` produces a tombstone.
+    pub value: Option<String>,
+    #[serde(default)]
+    pub headers: Vec<MessageHeader>,
+    /// Explicit partition, the configured partitioner picks one when unset.
+    pub partition: Option<i32>,
+    /// Milliseconds since the epoch, defaults to the time of sending.
+    pub timestamp: Option<i64>,
+    /// Serializes the key, then a JSON document, with a registry schema in the Confluent wire format.
+    pub key_schema: Option<SchemaTarget>,
+    /// Serializes the value, then a JSON document, with a registry schema in the Confluent wire format.
+    pub value_schema: Option<SchemaTarget>,
+    #[serde(flatten)]
+    pub settings: ProducerSettings,
+}
+
+/// A message as handed to librdkafka, keys and values already encoded.
+#[derive(Clone, Debug)]
+pub struct ProducerRecord {
+    pub topic: String,
+    pub key: Option<Vec<u8>>,
+    pub value: Option<Vec<u8>>,
+    /// In sending order, keys may repeat.
+    pub headers: Vec<(String, Option<Vec<u8>>)>,
+    pub partition: Option<i32>,
+    pub timestamp: Option<i64>,
+}
+impl From<&ProduceRequest> for ProducerRecord {
+    fn from(request: &ProduceRequest) -> Self {
+        Self {
+            topic: request.topic.to_owned(),
+            key: request.key.as_ref().map(|key| key.as_bytes().to_vec()),
+            value: request.value.as_ref().map(|value| value.as_bytes().to_vec()),
+            headers: request
+                .headers
+                .iter()
+                .map(|header| (header.key.to_owned(), header.value.as_ref().map(|value| value.as_bytes().to_vec())))
+                .collect(),
+            partition: request.partition,
+            timestamp: request.timestamp,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliveryReport {
+    pub topic: String,
+    pub partition: i32,
+    /// `-1` when sent without acknowledgement.
+    pub offset: i64,
+}
+
+pub struct KafkaProducer {
+    producer: FutureProducer,
+}
+
+impl KafkaProducer {
+    pub fn connect(cluster: &ClusterConfig, secrets: &SecretStore, settings: ProducerSettings) -> Result<Self, AppError> {
+        let mut client_config = cluster.client_config(secrets)?;
+        if let Some(acks) = settings.acks {
+            client_config.set("acks", acks.as_str());
+        }
+        if let Some(compression) = settings.compression {
+            client_config.set("compression.type", compression.as_str());
+        }
+        // Fail a delivery well before the librdkafka default of five minutes.
+        set_default(&mut client_config, "message.timeout.ms", "30000");
+
+        Ok(Self {
+            producer: client_config
+                .create::<FutureProducer>()
+                .map_err(|err| AppError::from(err).on(Resource::Cluster(cluster.name.to_owned())))?,
+        })
+    }
+
+    pub async fn send(&self, request: &ProducerRecord) -> Result<DeliveryReport, AppError> {
+        self.enqueue(request).await?.await
+    }
+
+    /// Hands `request` to librdkafka, waiting up to `QUEUE_TIMEOUT` for room in its queue.
+    /// The record is sent whether or not the returned future is polled, it resolves with the delivery report.
+    pub async fn enqueue(
+        &self,
+        request: &ProducerRecord,
+    ) -> Result<impl Future<Output = Result<DeliveryReport, AppError>> + Send + 'static, AppError> {
+        let headers = request.headers.iter().fold(OwnedHeaders::new(), |headers, (key, value)| {
+            headers.insert(Header {
+                key,
+                value: value.as_deref(),
+            })
+        });
+
+        let mut record: FutureRecord<'_, [u8], [u8]> = FutureRecord::to(&request.topic).headers(headers);
+        if let Some(key) = &request.key {
+            record = record.key(key);
+        }
+        if let Some(value) = &request.value {
+            record = record.payload(value);
+        }
+        if let Some(partition) = request.partition {
+            record = record.partition(partition);
+        }
+        if let Some(timestamp) = request.timestamp {
+            record = record.timestamp(timestamp);
+        }
+
+        let resource = match request.partition {
+            Some(partition) => Resource::Partition {
+                topic: request.topic.to_owned(),
+                partition,
+            },
+            None => Resource::Topic(request.topic.to_owned()),
+        };
+        let queue_deadline = Instant::now() + QUEUE_TIMEOUT;
+        let delivery = loop {
+            match self.producer.send_result(record) {
+                Ok(delivery) => break delivery,
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), returned))
+                    if Instant::now() < queue_deadline =>
+                {
+                    record = returned;
+                    sleep(QUEUE_RETRY_INTERVAL).await;
+                }
+                Err((err, _record)) => return Err(AppError::from(err).on(resource)),
+            }
+        };
+
+        let topic = request.topic.to_owned();
+        Ok(async move {
+            match delivery.await {
+                Ok(Ok((partition, offset))) => Ok(DeliveryReport {
+                    topic,
+                    partition,
+                    offset,
+                }),
+                Ok(Err((err, _message))) => Err(AppError::from(err).on(resource)),
+                Err(_canceled) => Err(AppError::internal("The producer closed before reporting the delivery")),
+            }
+        })
+    }
+}
@@ -0,0 +1,510 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc, task, time::sleep_until};
+
+use crate::core::error::AppError;
+
+use super::{
+    consumer::MessageEnvelope,
+    message::MessageBytes,
+    producer::{KafkaProducer, ProducerRecord, ProducerSettings},
+};
+
+/// Sends waiting for their delivery report at the same time.
+const MAX_IN_FLIGHT: usize = 100;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+/// Failures reported in the summary, the rest are only counted.
+const MAX_REPORTED_FAILURES: usize = 100;
+
+/// Columns of a CSV file to produce from, referenced by their name in the header row.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvColumns {
+    pub key: Option<String>,
+    pub value: String,
+    /// Columns sent as headers, named after the column.
+    #[serde(default)]
+    pub headers: Vec<String>,
+    /// Column with milliseconds since the epoch, needs `preserve_timestamps`.
+    pub timestamp: Option<String>,
+    /// Needs `preserve_partitions`.
+    pub partition: Option<String>,
+    /// An ASCII character, a comma when unset.
+    pub delimiter: Option<char>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "content")]
+pub enum ReplayFormat {
+    /// One `MessageEnvelope` per line, as exported from a consumer. Keys, payloads and header
+    /// values may also be plain strings.
+    Jsonl,
+    Csv(CsvColumns),
+    /// Every line is sent as the value of a message without key.
+    Lines,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayOptions {
+    /// Send records to the partition they were read from instead of using the partitioner.
+    #[serde(default)]
+    pub preserve_partitions: bool,
+    /// Keep the timestamps of the records instead of stamping them at send time.
+    #[serde(default)]
+    pub preserve_timestamps: bool,
+    /// Maximum messages per second, unlimited when unset.
+    pub rate_limit: Option<u32>,
+    #[serde(flatten)]
+    pub settings: ProducerSettings,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayProgress {
+    pub path: String,
+    pub topic: String,
+    pub read: u64,
+    pub delivered: u64,
+    pub failed: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayFailure {
+    /// 1-based line, or row for CSV files, of the record.
+    pub record: u64,
+    pub error: AppError,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplaySummary {
+    pub path: String,
+    pub topic: String,
+    pub delivered: u64,
+    pub failed: u64,
+    /// The first failures, unreadable records included.
+    pub failures: Vec<ReplayFailure>,
+    pub elapsed_ms: u128,
+}
+
+type Records = Box<dyn Iterator<Item = (u64, Result<ProducerRecord, AppError>)> + Send>;
+
+/// Streams the records of `path` into `topic`, reporting progress while sending.
+/// Unreadable records and failed deliveries are counted, they do not stop the replay.
+pub async fn produce_from_file(
+    producer: Arc<KafkaProducer>,
+    path: &Path,
+    topic: &str,
+    format: &ReplayFormat,
+    options: &ReplayOptions,
+    mut on_progress: impl FnMut(&ReplayProgress),
+) -> Result<ReplaySummary, AppError> {
+    let records = {
+        let (path, topic, format, options) = (path.to_owned(), topic.to_owned(), format.to_owned(), options.to_owned());
+        task::spawn_blocking(move || read_records(&path, &topic, &format, &options))
+            .await
+            .map_err(|err| AppError::internal(err.to_string()))??
+    };
+    // The file is read on a blocking thread, a record ahead of each one in flight.
+    let (sender, mut receiver) = mpsc::channel(MAX_IN_FLIGHT);
+    task::spawn_blocking(move || {
+        for record in records {
+            if sender.blocking_send(record).is_err() {
+                break;
+            }
+        }
+    });
+    let started = Instant::now();
+    let rate_limit = RateLimit::new(options.rate_limit, started);
+
+    let mut progress = ReplayProgress {
+        path: path.display().to_string(),
+        topic: topic.to_owned(),
+        read: 0,
+        delivered: 0,
+        failed: 0,
+    };
+    let mut failures = vec![];
+    let mut record_failure = |progress: &mut ReplayProgress, record: u64, error: AppError| {
+        progress.failed += 1;
+        if failures.len() < MAX_REPORTED_FAILURES {
+            failures.push(ReplayFailure { record, error });
+        }
+    };
+
+    let mut in_flight = FuturesUnordered::new();
+    let mut last_progress = Instant::now();
+    while let Some((record, request)) = receiver.recv().await {
+        progress.read += 1;
+        match request {
+            Ok(request) => match producer.enqueue(&request).await {
+                Ok(delivery) => in_flight.push(async move { (record, delivery.await) }),
+                Err(err) => record_failure(&mut progress, record, err),
+            },
+            Err(err) => record_failure(&mut progress, record, err),
+        }
+
+        // Records are already on their way, delivery reports are collected while waiting for the rate limit
+        // or for room when too many are outstanding.
+        let next_send = rate_limit.as_ref().map(|rate_limit| rate_limit.next_send(progress.read));
+        loop {
+            let reported = if in_flight.len() >= MAX_IN_FLIGHT {
+                in_flight.next().await
+            } else if let Some(next_send) = next_send.filter(|next_send| *next_send > Instant::now()) {
+                tokio::select! {
+                    reported = in_flight.next(), if !in_flight.is_empty() => reported,
+                    _ = sleep_until(next_send.into()) => None,
+                }
+            } else {
+                in_flight.next().now_or_never().flatten()
+            };
+            let Some((record, result)) = reported else {
+                break;
+            };
+            match result {
+                Ok(_) => progress.delivered += 1,
+                Err(err) => record_failure(&mut progress, record, err),
+            }
+        }
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            on_progress(&progress);
+            last_progress = Instant::now();
+        }
+    }
+    while let Some((record, result)) = in_flight.next().await {
+        match result {
+            Ok(_) => progress.delivered += 1,
+            Err(err) => record_failure(&mut progress, record, err),
+        }
+    }
+    on_progress(&progress);
+
+    Ok(ReplaySummary {
+        path: progress.path,
+        topic: progress.topic,
+        delivered: progress.delivered,
+        failed: progress.failed,
+        failures,
+        elapsed_ms: started.elapsed().as_millis(),
+    })
+}
+
+/// Spaces sends evenly to stay under a number of messages per second, counted from the start of the replay.
+struct RateLimit {
+    started: Instant,
+    interval: Duration,
+}
+
+impl RateLimit {
+    /// `None` when `limit` is unset or zero.
+    fn new(limit: Option<u32>, started: Instant) -> Option<Self> {
+        limit.filter(|limit| *limit > 0).map(|limit| Self {
+            started,
+            interval: Duration::from_secs_f64(1.0 / limit as f64),
+        })
+    }
+
+    /// When the next record may be sent once `sent` records are.
+    fn next_send(&self, sent: u64) -> Instant {
+        self.started + self.interval.mul_f64(sent as f64)
+    }
+}
+
+/// The records of `path` as they are sent to `topic`, numbered by line or by row for CSV files.
+pub fn read_records(path: &Path, topic: &str, format: &ReplayFormat, options: &ReplayOptions) -> Result<Records, AppError> {
+    let file = File::open(path)
+        .map_err(|err| AppError::Io(format!("Could not read '{}': {}", path.display(), err)))?;
+    let topic = topic.to_owned();
+    let options = options.to_owned();
+    let request = move |key: Option<Vec<u8>>,
+                        value: Option<Vec<u8>>,
+                        headers: Vec<(String, Option<Vec<u8>>)>,
+                        partition: Option<i32>,
+                        timestamp: Option<i64>| ProducerRecord {
+        topic: topic.to_owned(),
+        key,
+        value,
+        headers,
+        partition: partition.filter(|_| options.preserve_partitions),
+        timestamp: timestamp.filter(|_| options.preserve_timestamps),
+    };
+
+    let records: Records = match format {
+        ReplayFormat::Jsonl => Box::new(lines(file).filter_map(move |(record, line)| {
+            let parsed = line.and_then(|line| {
+                if line.trim().is_empty() {
+                    return Ok(None);
+                }
+                let envelope: MessageEnvelope<MessageBytes, MessageBytes> = serde_json::from_str(&line)
+                    .map_err(|err| AppError::invalid(format!("Not a message envelope: {}", err)))?;
+                let headers = envelope
+                    .headers
+                    .into_iter()
+                    .map(|header| (header.key, header.value.raw))
+                    .collect();
+                Ok(Some(request(
+                    envelope.key.raw,
+                    envelope.payload.raw,
+                    headers,
+                    Some(envelope.partition),
+                    Some(envelope.timestamp),
+                )))
+            });
+            parsed.transpose().map(|parsed| (record, parsed))
+        })),
+        ReplayFormat::Lines => Box::new(lines(file).filter_map(move |(record, line)| match line {
+            Ok(line) if line.is_empty() => None,
+            line => Some((record, line.map(|line| request(None, Some(line.into_bytes()), vec![], None, None)))),
+        })),
+        ReplayFormat::Csv(columns) => {
+            if columns.partition.is_some() && !options.preserve_partitions {
+                return Err(AppError::invalid("A partition column is only used when preserving partitions"));
+            }
+            if columns.timestamp.is_some() && !options.preserve_timestamps {
+                return Err(AppError::invalid("A timestamp column is only used when preserving timestamps"));
+            }
+            let delimiter = match columns.delimiter {
+                None => b',',
+                Some(delimiter) if delimiter.is_ascii() => delimiter as u8,
+                Some(delimiter) => {
+                    return Err(AppError::invalid(format!("CSV delimiter '{}' is not an ASCII character", delimiter)))
+                }
+            };
+            let mut reader = csv::ReaderBuilder::new()
+                .delimiter(delimiter)
+                .from_reader(BufReader::new(file));
+            let header_row = reader
+                .headers()
+                .map_err(|err| AppError::invalid(format!("Could not read CSV header row: {}", err)))?
+                .to_owned();
+            let column = |name: &String| {
+                header_row
+                    .iter()
+                    .position(|column| column == name)
+                    .ok_or_else(|| AppError::invalid(format!("CSV column '{}' does not exist", name)))
+            };
+            let key = columns.key.as_ref().map(column).transpose()?;
+            let value = column(&columns.value)?;
+            let timestamp = columns.timestamp.as_ref().map(column).transpose()?;
+            let partition = columns.partition.as_ref().map(column).transpose()?;
+            let headers = columns
+                .headers
+                .iter()
+                .map(|name| column(name).map(|index| (name.to_owned(), index)))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Box::new(reader.into_records().enumerate().map(move |(index, row)| {
+                // Row 1 is the header row.
+                let record = index as u64 + 2;
+                let parsed = row
+                    .map_err(|err| AppError::invalid(format!("Invalid CSV row: {}", err)))
+                    .and_then(|row| {
+                        let field = |index: Option<usize>| index.and_then(|index| row.get(index));
+                        let number = |index: Option<usize>, name: &str| {
+                            field(index)
+                                .filter(|value| !value.is_empty())
+                                .map(|value| {
+                                    value.parse::<i64>().map_err(|_| {
+                                        AppError::invalid(format!("Column {} is not a number: '{}'", name, value))
+                                    })
+                                })
+                                .transpose()
+                        };
+                        let message_headers = headers
+                            .iter()
+                            .map(|(key, index)| (key.to_owned(), row.get(*index).map(|value| value.as_bytes().to_vec())))
+                            .collect();
+                        let partition = number(partition, "partition")?
+                            .map(|partition| {
+                                i32::try_from(partition).map_err(|_| {
+                                    AppError::invalid(format!("Column partition is out of range: {}", partition))
+                                })
+                            })
+                            .transpose()?;
+                        Ok(request(
+                            field(key).filter(|key| !key.is_empty()).map(|key| key.as_bytes().to_vec()),
+                            field(Some(value)).map(|value| value.as_bytes().to_vec()),
+                            message_headers,
+                            partition,
+                            number(timestamp, "timestamp")?,
+                        ))
+                    });
+                (record, parsed)
+            }))
+        }
+    };
+    Ok(records)
+}
+
+fn lines(file: File) -> impl Iterator<Item = (u64, Result<String, AppError>)> {
+    BufReader::new(file).lines().enumerate().map(|(index, line)| {
+        let line = line.map_err(|err| AppError::Io(format!("Could not read line {}: {}", index + 1, err)));
+        (index as u64 + 1, line)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        time::{Duration, Instant},
+    };
+
+    use serde_json::json;
+
+    use super::{read_records, CsvColumns, RateLimit, ReplayFormat, ReplayOptions};
+    use crate::{core::error::AppError, kafka::producer::ProducerRecord};
+
+    type Read = Vec<(u64, Result<ProducerRecord, AppError>)>;
+
+    fn read(name: &str, content: &str, format: ReplayFormat, options: ReplayOptions) -> Result<Read, AppError> {
+        let path = std::env::temp_dir().join(format!("kafka-client-replay-{}-{}", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        let records = read_records(&path, "events", &format, &options).map(Iterator::collect);
+        fs::remove_file(&path).unwrap();
+        records
+    }
+
+    fn preserving() -> ReplayOptions {
+        ReplayOptions {
+            preserve_partitions: true,
+            preserve_timestamps: true,
+            ..Default::default()
+        }
+    }
+
+    fn columns() -> CsvColumns {
+        CsvColumns {
+            key: Some("id".to_owned()),
+            value: "body".to_owned(),
+            headers: vec!["trace".to_owned()],
+            timestamp: Some("at".to_owned()),
+            partition: Some("partition".to_owned()),
+            delimiter: None,
+        }
+    }
+
+    #[test]
+    fn jsonl_reads_exported_envelopes_and_plain_strings() {
+        let exported = json!({
+            "key": {"raw": "AAE="},
+            "partition": 3,
+            "offset": 7,
+            "headers": [{"key": "trace", "value": "t-1"}, {"key": "empty", "value": null}],
+            "payload": "hello",
+            "timestamp": 1700000000000i64
+        });
+        let nulls = json!({
+            "key": null,
+            "partition": 0,
+            "offset": 8,
+            "headers": [],
+            "payload": {"raw": null},
+            "timestamp": 1
+        });
+        let content = [exported.to_string(), String::new(), nulls.to_string(), "{not json".to_owned()].join("\n");
+
+        let records = read("envelopes.jsonl", &content, ReplayFormat::Jsonl, preserving()).unwrap();
+        let lines: Vec<u64> = records.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [1, 3, 4], "blank lines are skipped but still counted");
+
+        let first = records[0].1.as_ref().unwrap();
+        assert_eq!(first.topic, "events");
+        assert_eq!(first.key.as_deref(), Some(&[0u8, 1][..]));
+        assert_eq!(first.value.as_deref(), Some(&b"hello"[..]));
+        assert_eq!(first.headers, [("trace".to_owned(), Some(b"t-1".to_vec())), ("empty".to_owned(), None)]);
+        assert_eq!((first.partition, first.timestamp), (Some(3), Some(1700000000000)));
+
+        let second = records[1].1.as_ref().unwrap();
+        assert_eq!((second.key.as_ref(), second.value.as_ref()), (None, None), "null stays null");
+        assert!(matches!(records[2].1, Err(AppError::Invalid(_))));
+
+        let records = read("plain.jsonl", &content, ReplayFormat::Jsonl, ReplayOptions::default()).unwrap();
+        let first = records[0].1.as_ref().unwrap();
+        assert_eq!((first.partition, first.timestamp), (None, None), "left to the producer unless preserved");
+    }
+
+    #[test]
+    fn csv_reads_the_selected_columns() {
+        let content = "id,body,at,partition,trace\n\
+                       k1,\"hello, world\",1700000000000,2,t-1\n\
+                       ,plain,,,\n\
+                       k3,late,yesterday,1,t-3\n\
+                       k4,far,1,2147483648,t-4\n";
+        let records = read("rows.csv", content, ReplayFormat::Csv(columns()), preserving()).unwrap();
+        let rows: Vec<u64> = records.iter().map(|(row, _)| *row).collect();
+        assert_eq!(rows, [2, 3, 4, 5], "rows are numbered after the header row");
+
+        let first = records[0].1.as_ref().unwrap();
+        assert_eq!(first.key.as_deref(), Some(&b"k1"[..]));
+        assert_eq!(first.value.as_deref(), Some(&b"hello, world"[..]));
+        assert_eq!(first.headers, [("trace".to_owned(), Some(b"t-1".to_vec()))]);
+        assert_eq!((first.partition, first.timestamp), (Some(2), Some(1700000000000)));
+
+        let second = records[1].1.as_ref().unwrap();
+        assert_eq!(second.key, None, "an empty key column is no key");
+        assert_eq!((second.partition, second.timestamp), (None, None));
+
+        assert!(matches!(&records[2].1, Err(AppError::Invalid(message)) if message.contains("timestamp")));
+        assert!(matches!(&records[3].1, Err(AppError::Invalid(message)) if message.contains("out of range")));
+    }
+
+    #[test]
+    fn csv_uses_an_ascii_delimiter() {
+        let format = |delimiter| {
+            ReplayFormat::Csv(CsvColumns {
+                delimiter: Some(delimiter),
+                ..columns()
+            })
+        };
+        let content = "id;body;at;partition;trace\nk1;a,b;1;0;t-1\n";
+        let records = read("semicolon.csv", content, format(';'), preserving()).unwrap();
+        assert_eq!(records[0].1.as_ref().unwrap().value.as_deref(), Some(&b"a,b"[..]));
+
+        assert!(matches!(read("unicode.csv", content, format('§'), preserving()), Err(AppError::Invalid(_))));
+    }
+
+    #[test]
+    fn csv_columns_are_checked_before_reading() {
+        let content = "id,body\nk1,v1\n";
+        let missing = read("missing.csv", content, ReplayFormat::Csv(columns()), preserving());
+        assert!(matches!(missing, Err(AppError::Invalid(message)) if message.contains("'at'")));
+
+        let content = "id,body,at,partition,trace\nk1,v1,1,0,t-1\n";
+        let ignored = read("ignored.csv", content, ReplayFormat::Csv(columns()), ReplayOptions::default());
+        assert!(matches!(ignored, Err(AppError::Invalid(_))), "partition and timestamp columns need the flags");
+    }
+
+    #[test]
+    fn lines_become_values_without_keys() {
+        let records = read("values.txt", "first\n\n second \n", ReplayFormat::Lines, preserving()).unwrap();
+        let lines: Vec<u64> = records.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [1, 3], "empty lines are skipped");
+        let records: Vec<ProducerRecord> = records.into_iter().map(|(_, record)| record.unwrap()).collect();
+        assert!(records.iter().all(|record| record.key.is_none()));
+        assert_eq!(records[0].value.as_deref(), Some(&b"first"[..]));
+        assert_eq!(records[1].value.as_deref(), Some(&b" second "[..]), "whitespace is kept");
+    }
+
+    #[test]
+    fn rate_limit_spaces_sends_evenly() {
+        let started = Instant::now();
+        assert!(RateLimit::new(None, started).is_none());
+        assert!(RateLimit::new(Some(0), started).is_none(), "zero is unlimited");
+
+        let rate_limit = RateLimit::new(Some(4), started).unwrap();
+        assert_eq!(rate_limit.next_send(0), started);
+        assert_eq!(rate_limit.next_send(1), started + Duration::from_millis(250));
+        assert_eq!(rate_limit.next_send(8), started + Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,1052 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use prost_reflect::{
+    prost::Message, prost_types::FileDescriptorProto, DescriptorPool, DynamicMessage, FileDescriptor, MessageDescriptor,
+};
+use reqwest::{header, Certificate, Client, Identity, Method, RequestBuilder, Url};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use similar::{ChangeTag, TextDiff};
+
+use crate::core::{
+    error::AppError,
+    secrets::{SecretRef, SecretStore},
+};
+
+use super::{
+    deserializer::{AvroDeserializer, Deserializer, JsonDeserializer},
+    security::{PemSource, SslConfig},
+    serializer::{AvroSerializer, JsonSchemaSerializer, ProtobufSerializer, Serializer},
+};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a schema id that could not be fetched is answered with the same error, so messages are shown
+/// as raw bytes right away instead of each waiting for an unreachable registry.
+const FAILED_LOOKUP_RETRY: Duration = Duration::from_secs(30);
+/// First byte of every key or value in the Confluent wire format, followed by the schema id.
+const MAGIC_BYTE: u8 = 0;
+/// Makes the registry return Protobuf schemas as a base64 `FileDescriptorProto` instead of the `.proto`
+/// source, other schema types are returned as they are.
+const SERIALIZED: [(&str, &str); 1] = [("format", "serialized")];
+const ACCEPT: &str = "application/vnd.schemaregistry.v1+json, application/json";
+const SUBJECT_NOT_FOUND: i64 = 40401;
+const SCHEMA_NOT_FOUND: i64 = 40403;
+const VERSION_SOFT_DELETED: i64 = 40406;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SchemaRegistryConfig {
+    pub url: String,
+    /// Basic auth credentials, e.g. an API key and secret.
+    pub username: Option<String>,
+    pub password: Option<SecretRef>,
+    pub ssl: Option<SslConfig>,
+    /// Whether producing with a schema that is not registered under its subject yet registers it.
+    #[serde(default)]
+    pub auto_register: bool,
+}
+impl SchemaRegistryConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        Url::parse(&self.url)
+            .map_err(|err| AppError::invalid(format!("Schema registry URL '{}' is invalid: {}", self.url, err)))?;
+        if self.password.is_some() && self.username.is_none() {
+            return Err(AppError::invalid("Schema registry password needs a username"));
+        }
+        if let Some(ssl) = &self.ssl {
+            if matches!(ssl.key, Some(PemSource::Pem(_))) {
+                return Err(AppError::invalid(
+                    "Private keys can not be stored inline, keep them in the secret store",
+                ));
+            }
+            if ssl.key_password.is_some() {
+                return Err(AppError::invalid(
+                    "Encrypted private keys are not supported for the schema registry",
+                ));
+            }
+            if ssl.certificate.is_some() != ssl.key.is_some() {
+                return Err(AppError::invalid(
+                    "Schema registry client authentication needs both a certificate and a key",
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SchemaType {
+    /// The registry leaves the type out for Avro schemas.
+    #[default]
+    Avro,
+    Protobuf,
+    Json,
+}
+
+/// A schema defined in another subject, by the name it is imported or referenced with.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SchemaReference {
+    pub name: String,
+    pub subject: String,
+    pub version: i32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisteredSchema {
+    /// Only set when looked up by subject.
+    pub subject: Option<String>,
+    pub id: Option<u32>,
+    pub version: Option<i32>,
+    pub schema: String,
+    #[serde(default)]
+    pub schema_type: SchemaType,
+    #[serde(default)]
+    pub references: Vec<SchemaReference>,
+}
+
+/// Schema a key or value is produced with. An `id` is used as is, otherwise the `schema` as registered
+/// under `subject`, or `version` of `subject`, the latest when unset.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaTarget {
+    pub subject: Option<String>,
+    pub id: Option<u32>,
+    pub version: Option<i32>,
+    pub schema: Option<SchemaDefinition>,
+    /// Full name of the Protobuf message to write, the first message of the schema when unset.
+    pub message: Option<String>,
+}
+
+/// A schema to register or to check against a subject.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaDefinition {
+    pub schema: String,
+    #[serde(default)]
+    pub schema_type: SchemaType,
+    #[serde(default)]
+    pub references: Vec<SchemaReference>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CompatibilityLevel {
+    Backward,
+    BackwardTransitive,
+    Forward,
+    ForwardTransitive,
+    Full,
+    FullTransitive,
+    None,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompatibilityCheck {
+    #[serde(alias = "is_compatible")]
+    pub is_compatible: bool,
+    /// Why the schema is incompatible, empty when it is compatible.
+    #[serde(default)]
+    pub messages: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DiffChange {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DiffLine {
+    pub change: DiffChange,
+    pub text: String,
+}
+
+/// Line diff between two versions of a subject. Avro and JSON schemas are pretty printed first,
+/// so only changes to the schema itself show up.
+#[derive(Clone, Debug, Serialize)]
+pub struct SchemaDiff {
+    pub from: RegisteredSchema,
+    pub to: RegisteredSchema,
+    pub lines: Vec<DiffLine>,
+}
+impl SchemaDiff {
+    pub fn between(from: RegisteredSchema, to: RegisteredSchema) -> Self {
+        let (old, new) = (normalize(&from), normalize(&to));
+        let lines = TextDiff::from_lines(&old, &new)
+            .iter_all_changes()
+            .map(|change| DiffLine {
+                change: match change.tag() {
+                    ChangeTag::Equal => DiffChange::Equal,
+                    ChangeTag::Insert => DiffChange::Insert,
+                    ChangeTag::Delete => DiffChange::Delete,
+                },
+                text: change.value().trim_end_matches('\n').to_owned(),
+            })
+            .collect();
+        Self { from, to, lines }
+    }
+}
+
+fn normalize(schema: &RegisteredSchema) -> String {
+    let pretty = match schema.schema_type {
+        SchemaType::Avro | SchemaType::Json => serde_json::from_str::<Value>(&schema.schema)
+            .ok()
+            .and_then(|value| serde_json::to_string_pretty(&value).ok()),
+        SchemaType::Protobuf => None,
+    };
+    pretty.unwrap_or_else(|| schema.schema.to_owned())
+}
+
+/// Subjects of a topic under the default `<topic>-key` and `<topic>-value` naming.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TopicSubjects {
+    pub key: Option<String>,
+    pub value: Option<String>,
+}
+impl TopicSubjects {
+    pub fn for_topic(topic: &str, subjects: &HashSet<String>) -> Self {
+        let find = |suffix: &str| Some(format!("{}-{}", topic, suffix)).filter(|subject| subjects.contains(subject));
+        Self {
+            key: find("key"),
+            value: find("value"),
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+struct ErrorBody {
+    error_code: Option<i64>,
+    message: Option<String>,
+}
+
+/// Client for a Confluent compatible schema registry, schemas are cached by id since an id
+/// always refers to the same schema.
+pub struct SchemaRegistryClient {
+    http: Client,
+    base_url: Url,
+    credentials: Option<(String, Option<String>)>,
+    auto_register: bool,
+    deserializers: Mutex<HashMap<u32, Arc<dyn Deserializer>>>,
+    /// Schema ids whose deserializer could not be built, with when that was tried.
+    failed_deserializers: Mutex<HashMap<u32, (Instant, AppError)>>,
+    /// By schema id and Protobuf message.
+    serializers: Mutex<HashMap<(u32, Option<String>), Arc<dyn Serializer>>>,
+    /// Last listing of the subjects, to link topics to their subjects without asking every time.
+    subjects: Mutex<Option<(Instant, HashSet<String>)>>,
+}
+
+impl SchemaRegistryClient {
+    pub fn new(config: &SchemaRegistryConfig, secrets: &SecretStore) -> Result<Self, AppError> {
+        config.validate()?;
+        let mut builder = Client::builder().timeout(REQUEST_TIMEOUT);
+        if let Some(ssl) = &config.ssl {
+            if let Some(ca) = &ssl.ca {
+                for certificate in Certificate::from_pem_bundle(ca.load(secrets)?.as_bytes())
+                    .map_err(|err| AppError::invalid(format!("Invalid schema registry CA certificate: {}", err)))?
+                {
+                    builder = builder.add_root_certificate(certificate);
+                }
+            }
+            if let (Some(certificate), Some(key)) = (&ssl.certificate, &ssl.key) {
+                let pem = format!("{}\n{}", certificate.load(secrets)?, key.load(secrets)?);
+                let identity = Identity::from_pem(pem.as_bytes())
+                    .map_err(|err| AppError::invalid(format!("Invalid schema registry client certificate: {}", err)))?;
+                builder = builder.identity(identity);
+            }
+            builder = builder.danger_accept_invalid_hostnames(!ssl.verify_hostname);
+        }
+
+        let password = config
+            .password
+            .as_ref()
+            .map(|password| secrets.resolve(password))
+            .transpose()?;
+        Ok(Self {
+            http: builder.build().map_err(|err| AppError::internal(err.to_string()))?,
+            base_url: Url::parse(&config.url).map_err(|err| AppError::invalid(err.to_string()))?,
+            credentials: config.username.to_owned().map(|username| (username, password)),
+            auto_register: config.auto_register,
+            deserializers: Mutex::new(HashMap::new()),
+            failed_deserializers: Mutex::new(HashMap::new()),
+            serializers: Mutex::new(HashMap::new()),
+            subjects: Mutex::new(None),
+        })
+    }
+
+    pub async fn schema_by_id(&self, id: u32) -> Result<RegisteredSchema, AppError> {
+        self.get(&["schemas", "ids", &id.to_string()], &[]).await
+    }
+
+    /// `version` is a version number or `latest`.
+    pub async fn subject_version(&self, subject: &str, version: &str) -> Result<RegisteredSchema, AppError> {
+        self.get(&["subjects", subject, "versions", version], &[]).await
+    }
+
+    /// Subject names, soft deleted ones only with `deleted`.
+    pub async fn subjects(&self, deleted: bool) -> Result<Vec<String>, AppError> {
+        self.get(&["subjects"], &[("deleted", &deleted.to_string())]).await
+    }
+
+    /// The live subjects, listed again once the last listing is older than `max_age`.
+    pub async fn cached_subjects(&self, max_age: Duration) -> Result<HashSet<String>, AppError> {
+        if let Some((listed_at, subjects)) = self.subjects.lock()?.as_ref() {
+            if listed_at.elapsed() < max_age {
+                return Ok(subjects.to_owned());
+            }
+        }
+        let subjects: HashSet<String> = self.subjects(false).await?.into_iter().collect();
+        *self.subjects.lock()? = Some((Instant::now(), subjects.to_owned()));
+        Ok(subjects)
+    }
+
+    pub async fn versions(&self, subject: &str, deleted: bool) -> Result<Vec<i32>, AppError> {
+        self.get(&["subjects", subject, "versions"], &[("deleted", &deleted.to_string())])
+            .await
+    }
+
+    /// Registers `schema` as the next version of `subject`, unless it is registered already.
+    /// Returns the id of the schema.
+    pub async fn register(&self, subject: &str, schema: &SchemaDefinition) -> Result<u32, AppError> {
+        #[derive(Deserialize)]
+        struct Registered {
+            id: u32,
+        }
+        let request = self
+            .request(Method::POST, &["subjects", subject, "versions"])?
+            .json(schema);
+        let registered: Registered = self.send(request).await?;
+        self.invalidate_subjects()?;
+        Ok(registered.id)
+    }
+
+    /// Checks `schema` against `version` of `subject`, or against every version the compatibility
+    /// level of the subject asks for with `latest`. Anything is compatible with a subject that does not exist yet.
+    pub async fn check_compatibility(
+        &self,
+        subject: &str,
+        version: &str,
+        schema: &SchemaDefinition,
+    ) -> Result<CompatibilityCheck, AppError> {
+        let request = self
+            .request(
+                Method::POST,
+                &["compatibility", "subjects", subject, "versions", version],
+            )?
+            .query(&[("verbose", "true")])
+            .json(schema);
+        match self.send(request).await {
+            Err(AppError::SchemaRegistry {
+                code: Some(SUBJECT_NOT_FOUND),
+                ..
+            }) => Ok(CompatibilityCheck {
+                is_compatible: true,
+                messages: vec![],
+            }),
+            result => result,
+        }
+    }
+
+    /// Compatibility level of `subject`, falling back to the global level, or the global level without a subject.
+    pub async fn compatibility(&self, subject: Option<&str>) -> Result<CompatibilityLevel, AppError> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Config {
+            compatibility_level: CompatibilityLevel,
+        }
+        let config: Config = self.get(&config_path(subject), &[("defaultToGlobal", "true")]).await?;
+        Ok(config.compatibility_level)
+    }
+
+    pub async fn set_compatibility(
+        &self,
+        subject: Option<&str>,
+        level: CompatibilityLevel,
+    ) -> Result<CompatibilityLevel, AppError> {
+        #[derive(Deserialize)]
+        struct Config {
+            compatibility: CompatibilityLevel,
+        }
+        let request = self
+            .request(Method::PUT, &config_path(subject))?
+            .json(&json!({ "compatibility": level }));
+        let config: Config = self.send(request).await?;
+        Ok(config.compatibility)
+    }
+
+    /// Soft deletes `version` of `subject`, a permanent delete soft deletes it first when needed.
+    /// Returns the deleted version.
+    pub async fn delete_version(&self, subject: &str, version: &str, permanent: bool) -> Result<i32, AppError> {
+        let soft_deleted = self
+            .send::<i32>(self.request(Method::DELETE, &["subjects", subject, "versions", version])?)
+            .await;
+        let deleted = match soft_deleted {
+            Ok(deleted) if !permanent => deleted,
+            Ok(deleted) => self.delete_permanently(subject, &deleted.to_string()).await?,
+            Err(AppError::SchemaRegistry {
+                code: Some(VERSION_SOFT_DELETED),
+                ..
+            }) if permanent => self.delete_permanently(subject, version).await?,
+            Err(err) => return Err(err),
+        };
+        self.invalidate_subjects()?;
+        Ok(deleted)
+    }
+
+    async fn delete_permanently(&self, subject: &str, version: &str) -> Result<i32, AppError> {
+        let request = self
+            .request(Method::DELETE, &["subjects", subject, "versions", version])?
+            .query(&[("permanent", "true")]);
+        self.send(request).await
+    }
+
+    /// Diff from version `from` to version `to` of `subject`.
+    pub async fn diff(&self, subject: &str, from: &str, to: &str) -> Result<SchemaDiff, AppError> {
+        let from = self.subject_version(subject, from).await?;
+        let to = self.subject_version(subject, to).await?;
+        Ok(SchemaDiff::between(from, to))
+    }
+
+    fn invalidate_subjects(&self) -> Result<(), AppError> {
+        *self.subjects.lock()? = None;
+        Ok(())
+    }
+
+    /// Decodes a key or value in the Confluent wire format into JSON.
+    pub async fn decode(&self, bytes: &[u8]) -> Result<Value, AppError> {
+        let (id, data) = split_wire_format(bytes)?;
+        self.deserializer(id).await?.deserialize(data)
+    }
+
+    /// Deserializer for data written with schema `id`, fetched on first use. A failed fetch is not retried
+    /// for `FAILED_LOOKUP_RETRY`, its error is returned again meanwhile.
+    pub async fn deserializer(&self, id: u32) -> Result<Arc<dyn Deserializer>, AppError> {
+        if let Some(cached) = self.deserializers.lock()?.get(&id) {
+            return Ok(cached.clone());
+        }
+        if let Some((failed_at, error)) = self.failed_deserializers.lock()?.get(&id) {
+            if failed_at.elapsed() < FAILED_LOOKUP_RETRY {
+                return Err(error.to_owned());
+            }
+        }
+        match self.build_deserializer(id).await {
+            Ok(deserializer) => {
+                self.failed_deserializers.lock()?.remove(&id);
+                self.deserializers.lock()?.insert(id, deserializer.clone());
+                Ok(deserializer)
+            }
+            Err(err) => {
+                self.failed_deserializers
+                    .lock()?
+                    .insert(id, (Instant::now(), err.to_owned()));
+                Err(err)
+            }
+        }
+    }
+
+    async fn build_deserializer(&self, id: u32) -> Result<Arc<dyn Deserializer>, AppError> {
+        let schema = self.data_schema(id).await?;
+        Ok(match schema.schema_type {
+            SchemaType::Avro => {
+                let definitions = self.avro_definitions(schema).await?;
+                let definitions: Vec<&str> = definitions.iter().map(String::as_str).collect();
+                Arc::new(AvroDeserializer::with_references(&definitions)?)
+            }
+            SchemaType::Protobuf => Arc::new(ConfluentProtobufDeserializer {
+                file: self.protobuf_file(id, schema).await?,
+            }),
+            SchemaType::Json => Arc::new(JsonDeserializer),
+        })
+    }
+
+    /// Encodes `value` in the Confluent wire format with the schema `target` resolves to.
+    pub async fn encode(&self, target: &SchemaTarget, value: &Value) -> Result<Vec<u8>, AppError> {
+        let id = self.resolve_target(target).await?;
+        let data = self.serializer(id, target.message.as_deref()).await?.serialize(value)?;
+        let mut bytes = Vec::with_capacity(data.len() + 5);
+        bytes.push(MAGIC_BYTE);
+        bytes.extend(id.to_be_bytes());
+        bytes.extend(data);
+        Ok(bytes)
+    }
+
+    /// Id of the schema `target` points at, registering its schema first when that is allowed.
+    pub async fn resolve_target(&self, target: &SchemaTarget) -> Result<u32, AppError> {
+        if let Some(id) = target.id {
+            return Ok(id);
+        }
+        let subject = target
+            .subject
+            .as_deref()
+            .ok_or_else(|| AppError::invalid("A schema needs either an id or a subject"))?;
+        let registered = match &target.schema {
+            Some(schema) => match self.lookup(subject, schema).await {
+                Err(AppError::SchemaRegistry {
+                    code: Some(SUBJECT_NOT_FOUND | SCHEMA_NOT_FOUND),
+                    ..
+                }) if self.auto_register => return self.register(subject, schema).await,
+                Err(AppError::SchemaRegistry {
+                    code: Some(SUBJECT_NOT_FOUND | SCHEMA_NOT_FOUND),
+                    ..
+                }) => {
+                    return Err(AppError::invalid(format!(
+                        "The schema is not registered under '{}' and auto-registering is off for this registry",
+                        subject
+                    )))
+                }
+                result => result?,
+            },
+            None => {
+                let version = target
+                    .version
+                    .map_or("latest".to_owned(), |version| version.to_string());
+                self.subject_version(subject, &version).await?
+            }
+        };
+        registered
+            .id
+            .ok_or_else(|| AppError::internal(format!("The registry returned no id for subject '{}'", subject)))
+    }
+
+    /// The version of `subject` with exactly `schema`.
+    pub async fn lookup(&self, subject: &str, schema: &SchemaDefinition) -> Result<RegisteredSchema, AppError> {
+        self.send(self.request(Method::POST, &["subjects", subject])?.json(schema))
+            .await
+    }
+
+    /// Serializer writing data with schema `id`, as `message` for Protobuf schemas.
+    pub async fn serializer(&self, id: u32, message: Option<&str>) -> Result<Arc<dyn Serializer>, AppError> {
+        let key = (id, message.map(str::to_owned));
+        if let Some(cached) = self.serializers.lock()?.get(&key) {
+            return Ok(cached.clone());
+        }
+        let schema = self.data_schema(id).await?;
+        let serializer: Arc<dyn Serializer> = match schema.schema_type {
+            SchemaType::Avro => {
+                let definitions = self.avro_definitions(schema).await?;
+                let definitions: Vec<&str> = definitions.iter().map(String::as_str).collect();
+                Arc::new(AvroSerializer::with_references(&definitions)?)
+            }
+            SchemaType::Protobuf => {
+                let file = self.protobuf_file(id, schema).await?;
+                let descriptor = match message {
+                    Some(message) => file.messages().find_map(|top| find_message(top, message)),
+                    None => file.messages().next(),
+                }
+                .ok_or_else(|| {
+                    AppError::invalid(format!(
+                        "Protobuf schema {} has no message {}",
+                        id,
+                        message.unwrap_or_default()
+                    ))
+                })?;
+                Arc::new(ConfluentProtobufSerializer {
+                    indexes: message_indexes(&descriptor),
+                    inner: ProtobufSerializer::new(descriptor),
+                })
+            }
+            SchemaType::Json => {
+                let references: Vec<(String, String)> = self
+                    .resolve_references(&schema.references, &[])
+                    .await?
+                    .into_iter()
+                    .map(|(name, reference)| (name, reference.schema))
+                    .collect();
+                Arc::new(JsonSchemaSerializer::new(&schema.schema, &references)?)
+            }
+        };
+        self.serializers.lock()?.insert(key, serializer.clone());
+        Ok(serializer)
+    }
+
+    /// The definitions of the types `schema` references followed by `schema` itself.
+    async fn avro_definitions(&self, schema: RegisteredSchema) -> Result<Vec<String>, AppError> {
+        let mut definitions: Vec<String> = self
+            .resolve_references(&schema.references, &[])
+            .await?
+            .into_iter()
+            .map(|(_, reference)| reference.schema)
+            .collect();
+        // References of references come last from the worklist, but have to be parsed first.
+        definitions.reverse();
+        definitions.push(schema.schema);
+        Ok(definitions)
+    }
+
+    /// Schema `id` as needed to read or write data, Protobuf schemas in the serialized format.
+    async fn data_schema(&self, id: u32) -> Result<RegisteredSchema, AppError> {
+        self.get(&["schemas", "ids", &id.to_string()], &SERIALIZED).await
+    }
+
+    /// `schema` is schema `id` in the serialized format.
+    async fn protobuf_file(&self, id: u32, schema: RegisteredSchema) -> Result<FileDescriptor, AppError> {
+        let mut files = vec![];
+        for (name, reference) in self.resolve_references(&schema.references, &SERIALIZED).await? {
+            files.push(decode_file_descriptor(&reference.schema, name)?);
+        }
+        let main = decode_file_descriptor(&schema.schema, format!("schema_{}.proto", id))?;
+        let name = main.name().to_owned();
+        files.push(main);
+
+        // Starts from the well-known types, the registry does not list imports of those as references.
+        let mut pool = DescriptorPool::global();
+        pool.add_file_descriptor_protos(files)
+            .map_err(|err| AppError::invalid(format!("Invalid Protobuf schema {}: {}", id, err)))?;
+        pool.get_file_by_name(&name)
+            .ok_or_else(|| AppError::internal(format!("Protobuf schema {} is missing from its pool", id)))
+    }
+
+    /// `references` and everything they reference in turn, each subject version once.
+    async fn resolve_references(
+        &self,
+        references: &[SchemaReference],
+        query: &[(&str, &str)],
+    ) -> Result<Vec<(String, RegisteredSchema)>, AppError> {
+        let mut pending = references.to_vec();
+        let mut seen = HashSet::new();
+        let mut resolved = vec![];
+        while let Some(reference) = pending.pop() {
+            if !seen.insert((reference.subject.to_owned(), reference.version)) {
+                continue;
+            }
+            let schema: RegisteredSchema = self
+                .get(
+                    &[
+                        "subjects",
+                        &reference.subject,
+                        "versions",
+                        &reference.version.to_string(),
+                    ],
+                    query,
+                )
+                .await
+                .map_err(|err| err.context(format!("Reference '{}'", reference.name)))?;
+            pending.extend(schema.references.iter().cloned());
+            resolved.push((reference.name, schema));
+        }
+        Ok(resolved)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, segments: &[&str], query: &[(&str, &str)]) -> Result<T, AppError> {
+        self.send(self.request(Method::GET, segments)?.query(query)).await
+    }
+
+    fn request(&self, method: Method, segments: &[&str]) -> Result<RequestBuilder, AppError> {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .map_err(|_| AppError::invalid(format!("Schema registry URL '{}' can not have a path", self.base_url)))?
+            .pop_if_empty()
+            .extend(segments);
+
+        let mut request = self.http.request(method, url).header(header::ACCEPT, ACCEPT);
+        if let Some((username, password)) = &self.credentials {
+            request = request.basic_auth(username, password.as_ref());
+        }
+        Ok(request)
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, AppError> {
+        let response = request.send().await.map_err(|err| AppError::SchemaRegistry {
+            status: None,
+            code: None,
+            message: format!("Could not reach the schema registry: {}", err),
+        })?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body: ErrorBody = response.json().await.unwrap_or_default();
+            return Err(AppError::SchemaRegistry {
+                status: Some(status.as_u16()),
+                code: body.error_code,
+                message: body.message.unwrap_or_else(|| status.to_string()),
+            });
+        }
+        response.json().await.map_err(|err| AppError::SchemaRegistry {
+            status: Some(status.as_u16()),
+            code: None,
+            message: format!("Unexpected schema registry response: {}", err),
+        })
+    }
+}
+
+fn config_path(subject: Option<&str>) -> Vec<&str> {
+    match subject {
+        Some(subject) => vec!["config", subject],
+        None => vec!["config"],
+    }
+}
+
+/// Splits a key or value in the Confluent wire format into its schema id and the encoded data.
+pub fn split_wire_format(bytes: &[u8]) -> Result<(u32, &[u8]), AppError> {
+    match bytes {
+        [MAGIC_BYTE, rest @ ..] if rest.len() >= 4 => {
+            let (id, data) = rest.split_at(4);
+            Ok((u32::from_be_bytes([id[0], id[1], id[2], id[3]]), data))
+        }
+        _ => Err(AppError::invalid(
+            "Not in the schema registry wire format, the magic byte or schema id is missing",
+        )),
+    }
+}
+
+fn decode_file_descriptor(base64: &str, fallback_name: String) -> Result<FileDescriptorProto, AppError> {
+    let bytes = BASE64
+        .decode(base64)
+        .map_err(|err| AppError::invalid(format!("Serialized Protobuf schema is not base64: {}", err)))?;
+    let mut file = FileDescriptorProto::decode(bytes.as_slice())
+        .map_err(|err| AppError::invalid(format!("Invalid serialized Protobuf schema: {}", err)))?;
+    // Imports refer to references by name, the registry does not always keep it in the descriptor.
+    file.name = Some(fallback_name);
+    Ok(file)
+}
+
+/// Protobuf data in the wire format starts with the indexes of its message type within the schema.
+struct ConfluentProtobufDeserializer {
+    file: FileDescriptor,
+}
+impl ConfluentProtobufDeserializer {
+    fn message(&self, indexes: &[usize]) -> Option<MessageDescriptor> {
+        let (first, nested) = indexes.split_first()?;
+        let mut message = self.file.messages().nth(*first)?;
+        for index in nested {
+            let child = message.child_messages().nth(*index)?;
+            message = child;
+        }
+        Some(message)
+    }
+}
+impl Deserializer for ConfluentProtobufDeserializer {
+    fn deserialize(&self, bytes: &[u8]) -> Result<Value, AppError> {
+        let mut data = bytes;
+        let count = read_zigzag(&mut data)?;
+        // A single zero stands for the first message of the schema.
+        let indexes = match count {
+            0 => vec![0],
+            count => (0..count)
+                .map(|_| read_zigzag(&mut data).map(|index| index as usize))
+                .collect::<Result<Vec<_>, _>>()?,
+        };
+        let descriptor = self
+            .message(&indexes)
+            .ok_or_else(|| AppError::invalid(format!("Protobuf schema has no message at {:?}", indexes)))?;
+        let message = DynamicMessage::decode(descriptor.to_owned(), data)
+            .map_err(|err| AppError::invalid(format!("Not a valid '{}' message: {}", descriptor.full_name(), err)))?;
+        serde_json::to_value(&message).map_err(|err| AppError::internal(err.to_string()))
+    }
+}
+
+fn read_zigzag(data: &mut &[u8]) -> Result<i64, AppError> {
+    let mut value: u64 = 0;
+    for shift in (0..64).step_by(7) {
+        let (byte, rest) = data
+            .split_first()
+            .ok_or_else(|| AppError::invalid("Protobuf message indexes are truncated"))?;
+        *data = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok((value >> 1) as i64 ^ -((value & 1) as i64));
+        }
+    }
+    Err(AppError::invalid("Protobuf message index is too long"))
+}
+
+/// Writes the indexes of its message type ahead of the Protobuf data, see `ConfluentProtobufDeserializer`.
+struct ConfluentProtobufSerializer {
+    inner: ProtobufSerializer,
+    indexes: Vec<i64>,
+}
+impl Serializer for ConfluentProtobufSerializer {
+    fn serialize(&self, value: &Value) -> Result<Vec<u8>, AppError> {
+        let mut bytes = vec![];
+        // The first message of the schema is written as a single zero.
+        if self.indexes == [0] {
+            write_zigzag(&mut bytes, 0);
+        } else {
+            write_zigzag(&mut bytes, self.indexes.len() as i64);
+            self.indexes.iter().for_each(|index| write_zigzag(&mut bytes, *index));
+        }
+        bytes.extend(self.inner.serialize(value)?);
+        Ok(bytes)
+    }
+}
+
+fn find_message(message: MessageDescriptor, name: &str) -> Option<MessageDescriptor> {
+    if message.full_name() == name {
+        return Some(message);
+    }
+    let children: Vec<MessageDescriptor> = message.child_messages().collect();
+    children.into_iter().find_map(|child| find_message(child, name))
+}
+
+/// Position of `message` within its parent, for each level from the top of the file.
+fn message_indexes(message: &MessageDescriptor) -> Vec<i64> {
+    let position = |mut siblings: Box<dyn Iterator<Item = MessageDescriptor> + '_>, message: &MessageDescriptor| {
+        siblings
+            .position(|sibling| sibling.full_name() == message.full_name())
+            .unwrap_or_default() as i64
+    };
+    let mut indexes = vec![];
+    let mut current = message.to_owned();
+    while let Some(parent) = current.parent_message() {
+        indexes.push(position(Box::new(parent.child_messages()), &current));
+        current = parent;
+    }
+    let file = current.parent_file();
+    indexes.push(position(Box::new(file.messages()), &current));
+    indexes.reverse();
+    indexes
+}
+
+fn write_zigzag(bytes: &mut Vec<u8>, value: i64) {
+    let mut value = ((value << 1) ^ (value >> 63)) as u64;
+    while value >= 0x80 {
+        bytes.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+    use prost_reflect::prost::Message;
+    use prost_reflect::prost_types::{
+        field_descriptor_proto::{Label, Type},
+        DescriptorProto, FieldDescriptorProto, FileDescriptorProto,
+    };
+    use serde_json::{json, Value};
+
+    use super::{
+        read_zigzag, split_wire_format, write_zigzag, SchemaRegistryClient, SchemaRegistryConfig, FAILED_LOOKUP_RETRY,
+    };
+    use crate::{core::secrets::SecretStore, kafka::serializer::{AvroSerializer, Serializer}};
+
+    /// Serves `routes` by path and query, answers 404 with a registry error body otherwise.
+    /// Returns the client and the number of requests the stub received.
+    fn stub_registry(routes: HashMap<String, Value>) -> (SchemaRegistryClient, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                counter.fetch_add(1, Ordering::SeqCst);
+
+                let target = request_line.split(' ').nth(1).unwrap_or_default().trim_end_matches('?');
+                let (status, body) = match routes.get(target) {
+                    Some(body) => ("200 OK", body.to_string()),
+                    None => ("404 Not Found", json!({"error_code": 40403, "message": "Schema not found"}).to_string()),
+                };
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        let config = SchemaRegistryConfig {
+            url,
+            username: None,
+            password: None,
+            ssl: None,
+            auto_register: false,
+        };
+        (SchemaRegistryClient::new(&config, &SecretStore::in_memory()).unwrap(), requests)
+    }
+
+    fn wire_format(id: u32, data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0];
+        bytes.extend(id.to_be_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    fn field(name: &str, number: i32, field_type: Type) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_owned()),
+            number: Some(number),
+            label: Some(Label::Optional as i32),
+            r#type: Some(field_type as i32),
+            json_name: Some(name.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    /// `example.User` with a nested `Address`, then `example.Event`.
+    fn protobuf_schema() -> String {
+        let file = FileDescriptorProto {
+            name: Some("user.proto".to_owned()),
+            package: Some("example".to_owned()),
+            syntax: Some("proto3".to_owned()),
+            message_type: vec![
+                DescriptorProto {
+                    name: Some("User".to_owned()),
+                    field: vec![field("name", 1, Type::String)],
+                    nested_type: vec![DescriptorProto {
+                        name: Some("Address".to_owned()),
+                        field: vec![field("zip", 1, Type::String)],
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                },
+                DescriptorProto {
+                    name: Some("Event".to_owned()),
+                    field: vec![field("count", 1, Type::Int32)],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        BASE64.encode(file.encode_to_vec())
+    }
+
+    #[test]
+    fn wire_format_splits_schema_id_from_data() {
+        let bytes = wire_format(258, &[7, 8]);
+        let (id, data) = split_wire_format(&bytes).unwrap();
+        assert_eq!((id, data), (258, &[7u8, 8][..]));
+        assert_eq!(split_wire_format(&[0, 0, 0, 0, 1]).unwrap(), (1, &[][..]));
+
+        assert!(split_wire_format(&[1, 0, 0, 0, 1, 7]).is_err(), "wrong magic byte");
+        assert!(split_wire_format(&[0, 0, 0, 1]).is_err(), "schema id cut short");
+        assert!(split_wire_format(&[]).is_err());
+    }
+
+    #[test]
+    fn message_indexes_are_zigzag_varints() {
+        let mut data: &[u8] = &[0x00, 0x02, 0x01, 0x03, 0x80, 0x01, 0xff];
+        let decoded: Vec<i64> = (0..5).map(|_| read_zigzag(&mut data).unwrap()).collect();
+        assert_eq!(decoded, [0, 1, -1, -2, 64]);
+        assert_eq!(data, [0xff], "only the varints are consumed");
+        assert!(read_zigzag(&mut data).is_err(), "a continuation byte without an end is truncated");
+
+        for value in [0, 1, -1, 63, -64, 300, i64::MAX, i64::MIN] {
+            let mut bytes = vec![];
+            write_zigzag(&mut bytes, value);
+            assert_eq!(read_zigzag(&mut bytes.as_slice()).unwrap(), value);
+        }
+    }
+
+    #[tokio::test]
+    async fn decodes_avro_with_references_and_caches_the_deserializer() {
+        let address = json!({
+            "type": "record",
+            "name": "Address",
+            "namespace": "example",
+            "fields": [{"name": "zip", "type": "string"}]
+        });
+        let user = json!({
+            "type": "record",
+            "name": "User",
+            "namespace": "example",
+            "fields": [{"name": "name", "type": "string"}, {"name": "address", "type": "Address"}]
+        });
+        let (registry, requests) = stub_registry(HashMap::from([
+            (
+                "/schemas/ids/1?format=serialized".to_owned(),
+                json!({
+                    "schema": user.to_string(),
+                    "references": [{"name": "example.Address", "subject": "address", "version": 1}]
+                }),
+            ),
+            (
+                "/subjects/address/versions/1".to_owned(),
+                json!({"subject": "address", "id": 2, "version": 1, "schema": address.to_string()}),
+            ),
+        ]));
+
+        let value = json!({"name": "ada", "address": {"zip": "1234"}});
+        let data = AvroSerializer::with_references(&[&address.to_string(), &user.to_string()])
+            .unwrap()
+            .serialize(&value)
+            .unwrap();
+        assert_eq!(registry.decode(&wire_format(1, &data)).await.unwrap(), value);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        assert_eq!(registry.decode(&wire_format(1, &data)).await.unwrap(), value);
+        assert_eq!(requests.load(Ordering::SeqCst), 2, "the second decode is served from the cache");
+    }
+
+    #[tokio::test]
+    async fn decodes_json_schema_data() {
+        let (registry, _) = stub_registry(HashMap::from([(
+            "/schemas/ids/3?format=serialized".to_owned(),
+            json!({"schema": r#"{"type": "object"}"#, "schemaType": "JSON"}),
+        )]));
+
+        let value = registry.decode(&wire_format(3, br#"{"id": 1, "tags": ["a"]}"#)).await.unwrap();
+        assert_eq!(value, json!({"id": 1, "tags": ["a"]}));
+        assert!(registry.decode(&wire_format(3, b"{not json")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn decodes_protobuf_messages_by_their_indexes() {
+        let (registry, requests) = stub_registry(HashMap::from([(
+            "/schemas/ids/4?format=serialized".to_owned(),
+            json!({"schema": protobuf_schema(), "schemaType": "PROTOBUF"}),
+        )]));
+
+        // A single zero stands for the first message.
+        let user = [&[0x00][..], &[0x0a, 0x03], b"ada"].concat();
+        assert_eq!(registry.decode(&wire_format(4, &user)).await.unwrap(), json!({"name": "ada"}));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // One index: the second top level message.
+        let event = [0x02, 0x02, 0x08, 0x05];
+        assert_eq!(registry.decode(&wire_format(4, &event)).await.unwrap(), json!({"count": 5}));
+
+        // Two indexes: the first message nested in the first message.
+        let address = [&[0x04, 0x00, 0x00][..], &[0x0a, 0x02], b"42"].concat();
+        assert_eq!(registry.decode(&wire_format(4, &address)).await.unwrap(), json!({"zip": "42"}));
+
+        let missing = [0x02, 0x06];
+        assert!(registry.decode(&wire_format(4, &missing)).await.is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 1, "the schema is fetched once");
+    }
+
+    #[tokio::test]
+    async fn unknown_schema_ids_report_the_registry_error() {
+        let (registry, requests) = stub_registry(HashMap::new());
+        for _ in 0..2 {
+            match registry.decode(&wire_format(9, &[])).await {
+                Err(crate::core::error::AppError::SchemaRegistry { status, code, .. }) => {
+                    assert_eq!((status, code), (Some(404), Some(40403)))
+                }
+                other => panic!("expected a registry error, got {:?}", other.map(|_| ())),
+            }
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 1, "the failed lookup is not repeated right away");
+
+        registry.failed_deserializers.lock().unwrap().get_mut(&9).unwrap().0 -= FAILED_LOOKUP_RETRY;
+        assert!(registry.decode(&wire_format(9, &[])).await.is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 2, "the lookup is retried once the failure is old enough");
+    }
+}
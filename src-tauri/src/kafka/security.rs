@@ -0,0 +1,219 @@
+use std::fs;
+
+use rdkafka::ClientConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::core::{
+    error::AppError,
+    secrets::{SecretRef, SecretStore},
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SecurityProtocol {
+    #[default]
+    Plaintext,
+    Ssl,
+    SaslPlaintext,
+    SaslSsl,
+}
+impl SecurityProtocol {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Plaintext => "plaintext",
+            Self::Ssl => "ssl",
+            Self::SaslPlaintext => "sasl_plaintext",
+            Self::SaslSsl => "sasl_ssl",
+        }
+    }
+    pub fn uses_sasl(&self) -> bool {
+        matches!(self, Self::SaslPlaintext | Self::SaslSsl)
+    }
+    pub fn uses_ssl(&self) -> bool {
+        matches!(self, Self::Ssl | Self::SaslSsl)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SaslMechanism {
+    #[serde(rename = "PLAIN")]
+    Plain,
+    #[serde(rename = "SCRAM-SHA-256")]
+    ScramSha256,
+    #[serde(rename = "SCRAM-SHA-512")]
+    ScramSha512,
+    #[serde(rename = "OAUTHBEARER")]
+    OAuthBearer,
+}
+impl SaslMechanism {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Plain => "PLAIN",
+            Self::ScramSha256 => "SCRAM-SHA-256",
+            Self::ScramSha512 => "SCRAM-SHA-512",
+            Self::OAuthBearer => "OAUTHBEARER",
+        }
+    }
+}
+
+/// Client credentials used to fetch tokens from an OIDC provider for `OAUTHBEARER`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OAuthBearerConfig {
+    pub token_endpoint_url: String,
+    pub client_id: String,
+    pub client_secret: SecretRef,
+    pub scope: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SaslConfig {
+    pub mechanism: SaslMechanism,
+    pub username: Option<String>,
+    pub password: Option<SecretRef>,
+    pub oauthbearer: Option<OAuthBearerConfig>,
+}
+
+/// A certificate or key, either as a path on disk, inline PEM content, or PEM content
+/// kept in the secret store.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "content")]
+pub enum PemSource {
+    Path(String),
+    Pem(String),
+    Secret(SecretRef),
+}
+impl PemSource {
+    /// The PEM content, read from disk or the secret store when needed.
+    pub fn load(&self, secrets: &SecretStore) -> Result<String, AppError> {
+        match self {
+            Self::Path(path) => fs::read_to_string(path)
+                .map_err(|err| AppError::Io(format!("Could not read '{}': {}", path, err))),
+            Self::Pem(pem) => Ok(pem.to_owned()),
+            Self::Secret(secret) => secrets.resolve(secret),
+        }
+    }
+
+    fn apply(
+        &self,
+        config: &mut ClientConfig,
+        secrets: &SecretStore,
+        location_key: &str,
+        pem_key: &str,
+    ) -> Result<(), AppError> {
+        match self {
+            Self::Path(path) => config.set(location_key, path),
+            Self::Pem(pem) => config.set(pem_key, pem),
+            Self::Secret(secret) => config.set(pem_key, secrets.resolve(secret)?),
+        };
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SslConfig {
+    pub ca: Option<PemSource>,
+    pub certificate: Option<PemSource>,
+    pub key: Option<PemSource>,
+    pub key_password: Option<SecretRef>,
+    #[serde(default = "default_verify_hostname")]
+    pub verify_hostname: bool,
+}
+fn default_verify_hostname() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SecurityConfig {
+    #[serde(default)]
+    pub protocol: SecurityProtocol,
+    pub sasl: Option<SaslConfig>,
+    pub ssl: Option<SslConfig>,
+}
+impl SecurityConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        let inline_key = self
+            .ssl
+            .as_ref()
+            .is_some_and(|ssl| matches!(ssl.key, Some(PemSource::Pem(_))));
+        if inline_key {
+            return Err(AppError::invalid(
+                "Private keys can not be stored inline, keep them in the secret store",
+            ));
+        }
+        if !self.protocol.uses_sasl() {
+            return Ok(());
+        }
+
+        let sasl = self.sasl.as_ref().ok_or_else(|| {
+            AppError::invalid(format!(
+                "Security protocol '{}' requires SASL settings",
+                self.protocol.as_str()
+            ))
+        })?;
+        match sasl.mechanism {
+            SaslMechanism::OAuthBearer if sasl.oauthbearer.is_none() => Err(AppError::invalid(
+                "SASL mechanism 'OAUTHBEARER' requires a token endpoint, client id and client secret",
+            )),
+            SaslMechanism::Plain | SaslMechanism::ScramSha256 | SaslMechanism::ScramSha512
+                if sasl.username.is_none() || sasl.password.is_none() =>
+            {
+                Err(AppError::invalid(format!(
+                    "SASL mechanism '{}' requires a username and password",
+                    sasl.mechanism.as_str()
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Sets the librdkafka security properties on `config`, resolving referenced secrets.
+    pub fn apply(&self, config: &mut ClientConfig, secrets: &SecretStore) -> Result<(), AppError> {
+        config.set("security.protocol", self.protocol.as_str());
+
+        if let Some(sasl) = self.sasl.as_ref().filter(|_| self.protocol.uses_sasl()) {
+            config.set("sasl.mechanisms", sasl.mechanism.as_str());
+            match sasl.mechanism {
+                SaslMechanism::OAuthBearer => {
+                    if let Some(oauth) = &sasl.oauthbearer {
+                        config
+                            .set("sasl.oauthbearer.method", "oidc")
+                            .set("sasl.oauthbearer.token.endpoint.url", &oauth.token_endpoint_url)
+                            .set("sasl.oauthbearer.client.id", &oauth.client_id)
+                            .set("sasl.oauthbearer.client.secret", secrets.resolve(&oauth.client_secret)?);
+                        if let Some(scope) = &oauth.scope {
+                            config.set("sasl.oauthbearer.scope", scope);
+                        }
+                    }
+                }
+                _ => {
+                    if let Some(username) = &sasl.username {
+                        config.set("sasl.username", username);
+                    }
+                    if let Some(password) = &sasl.password {
+                        config.set("sasl.password", secrets.resolve(password)?);
+                    }
+                }
+            }
+        }
+
+        if let Some(ssl) = self.ssl.as_ref().filter(|_| self.protocol.uses_ssl()) {
+            if let Some(ca) = &ssl.ca {
+                ca.apply(config, secrets, "ssl.ca.location", "ssl.ca.pem")?;
+            }
+            if let Some(certificate) = &ssl.certificate {
+                certificate.apply(config, secrets, "ssl.certificate.location", "ssl.certificate.pem")?;
+            }
+            if let Some(key) = &ssl.key {
+                key.apply(config, secrets, "ssl.key.location", "ssl.key.pem")?;
+            }
+            if let Some(key_password) = &ssl.key_password {
+                config.set("ssl.key.password", secrets.resolve(key_password)?);
+            }
+            config.set(
+                "ssl.endpoint.identification.algorithm",
+                if ssl.verify_hostname { "https" } else { "none" },
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,654 @@
+use std::collections::HashMap;
+
+use apache_avro::{to_avro_datum_schemata, types::Value as AvroValue, Schema};
+use prost_reflect::{prost::Message, DynamicMessage, MessageDescriptor};
+use serde_json::{Map, Value};
+
+use crate::core::error::AppError;
+
+/// Turns a JSON document into the bytes of a key or value, the reverse of a `Deserializer`.
+/// Errors name the path of the offending field, e.g. `$.address.zip`.
+pub trait Serializer: Send + Sync {
+    fn serialize(&self, value: &Value) -> Result<Vec<u8>, AppError>;
+}
+
+const AVRO_PRIMITIVES: [&str; 8] = ["null", "boolean", "int", "long", "float", "double", "bytes", "string"];
+
+pub struct AvroSerializer {
+    schema: Schema,
+    references: Vec<Schema>,
+    /// The schema of the data as JSON, walked to build the datum.
+    definition: Value,
+    /// Named types of the schema and its references by full name, with their namespace.
+    named: HashMap<String, (Value, String)>,
+}
+impl AvroSerializer {
+    /// `definitions` end with the schema of the data, the ones before it define the types it references.
+    pub fn with_references(definitions: &[&str]) -> Result<Self, AppError> {
+        let mut schemas = Schema::parse_list(definitions)
+            .map_err(|err| AppError::invalid(format!("Invalid Avro schema: {}", err)))?;
+        let schema = schemas
+            .pop()
+            .ok_or_else(|| AppError::invalid("Avro schema is missing"))?;
+
+        let mut named = HashMap::new();
+        let mut definition = Value::Null;
+        for text in definitions {
+            definition =
+                serde_json::from_str(text).map_err(|err| AppError::invalid(format!("Invalid Avro schema: {}", err)))?;
+            collect_named(&definition, "", &mut named);
+        }
+        Ok(Self {
+            schema,
+            references: schemas,
+            definition,
+            named,
+        })
+    }
+}
+impl Serializer for AvroSerializer {
+    fn serialize(&self, value: &Value) -> Result<Vec<u8>, AppError> {
+        let datum = AvroDatum { named: &self.named }
+            .build(value, &self.definition, "", "$")
+            .map_err(AppError::invalid)?;
+        to_avro_datum_schemata(&self.schema, self.references.iter().collect(), datum)
+            .map_err(|err| AppError::invalid(format!("Could not encode as Avro: {}", err)))
+    }
+}
+
+fn full_name(name: &str, namespace: &str) -> String {
+    if name.contains('.') || namespace.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}.{}", namespace, name)
+    }
+}
+
+fn collect_named(schema: &Value, namespace: &str, named: &mut HashMap<String, (Value, String)>) {
+    match schema {
+        Value::Array(branches) => branches
+            .iter()
+            .for_each(|branch| collect_named(branch, namespace, named)),
+        Value::Object(definition) => {
+            let mut namespace = namespace.to_owned();
+            if let Some(name) = definition.get("name").and_then(Value::as_str) {
+                let namespace_of_type = definition
+                    .get("namespace")
+                    .and_then(Value::as_str)
+                    .unwrap_or(&namespace);
+                let name = full_name(name, namespace_of_type);
+                namespace = name
+                    .rsplit_once('.')
+                    .map(|(namespace, _)| namespace.to_owned())
+                    .unwrap_or_default();
+                named.insert(name, (schema.to_owned(), namespace.to_owned()));
+            }
+            for field in definition.get("fields").and_then(Value::as_array).into_iter().flatten() {
+                if let Some(field_type) = field.get("type") {
+                    collect_named(field_type, &namespace, named);
+                }
+            }
+            for key in ["type", "items", "values"] {
+                if let Some(inner) = definition.get(key).filter(|inner| !inner.is_string()) {
+                    collect_named(inner, &namespace, named);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Builds an Avro datum from JSON by walking the schema, so mismatches are reported with their path.
+struct AvroDatum<'a> {
+    named: &'a HashMap<String, (Value, String)>,
+}
+impl AvroDatum<'_> {
+    fn build(&self, value: &Value, schema: &Value, namespace: &str, path: &str) -> Result<AvroValue, String> {
+        match schema {
+            Value::String(name) if AVRO_PRIMITIVES.contains(&name.as_str()) => primitive(value, name, path),
+            Value::String(name) => {
+                let (definition, namespace) = self
+                    .named
+                    .get(&full_name(name, namespace))
+                    .or_else(|| self.named.get(name))
+                    .ok_or_else(|| format!("{}: the schema references unknown type '{}'", path, name))?;
+                self.build(value, definition, namespace, path)
+            }
+            Value::Array(branches) => self.union(value, branches, namespace, path),
+            Value::Object(definition) => self.complex(value, definition, namespace, path),
+            _ => Err(format!("{}: the schema is invalid", path)),
+        }
+    }
+
+    fn union(&self, value: &Value, branches: &[Value], namespace: &str, path: &str) -> Result<AvroValue, String> {
+        // The Avro JSON encoding wraps non-null union values in `{"<type name>": value}`, the namespace
+        // of a named type may be left out here.
+        if let Some((name, inner)) = value
+            .as_object()
+            .filter(|object| object.len() == 1)
+            .and_then(|o| o.iter().next())
+        {
+            let matches = |branch: &Value| {
+                let type_name = self.type_name(branch, namespace);
+                type_name == *name || type_name.rsplit('.').next() == Some(name.as_str())
+            };
+            if let Some(index) = branches.iter().position(matches) {
+                let datum = self.build(inner, &branches[index], namespace, path)?;
+                return Ok(AvroValue::Union(index as u32, Box::new(datum)));
+            }
+        }
+
+        let mut errors = vec![];
+        for (index, branch) in branches.iter().enumerate() {
+            match self.build(value, branch, namespace, path) {
+                Ok(datum) => return Ok(AvroValue::Union(index as u32, Box::new(datum))),
+                Err(err) => errors.push(err),
+            }
+        }
+        // With a single non-null type, e.g. an optional record, its error is the one that matters.
+        let non_null: Vec<usize> = (0..branches.len()).filter(|i| branches[*i] != "null").collect();
+        match non_null[..] {
+            [index] if !value.is_null() => Err(errors.swap_remove(index)),
+            _ => Err(format!(
+                "{}: {} matches none of the union types {}",
+                path,
+                describe(value),
+                branches
+                    .iter()
+                    .map(|branch| self.type_name(branch, namespace))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+
+    fn complex(
+        &self,
+        value: &Value,
+        definition: &Map<String, Value>,
+        namespace: &str,
+        path: &str,
+    ) -> Result<AvroValue, String> {
+        let type_name = definition.get("type").and_then(Value::as_str).unwrap_or_default();
+        let namespace = match definition.get("name").and_then(Value::as_str) {
+            Some(name) => {
+                let name = full_name(
+                    name,
+                    definition.get("namespace").and_then(Value::as_str).unwrap_or(namespace),
+                );
+                name.rsplit_once('.')
+                    .map(|(namespace, _)| namespace.to_owned())
+                    .unwrap_or_default()
+            }
+            None => namespace.to_owned(),
+        };
+        match type_name {
+            "record" | "error" => {
+                let object = value
+                    .as_object()
+                    .ok_or_else(|| format!("{}: expected an object, got {}", path, describe(value)))?;
+                let fields = definition
+                    .get("fields")
+                    .and_then(Value::as_array)
+                    .cloned()
+                    .unwrap_or_default();
+                let names: Vec<&str> = fields.iter().filter_map(|field| field.get("name")?.as_str()).collect();
+                if let Some(unknown) = object.keys().find(|key| !names.contains(&key.as_str())) {
+                    return Err(format!("{}.{}: not a field of the record", path, unknown));
+                }
+
+                let mut record = vec![];
+                for field in fields.iter() {
+                    let name = field.get("name").and_then(Value::as_str).unwrap_or_default();
+                    let field_type = field.get("type").unwrap_or(&Value::Null);
+                    let field_path = format!("{}.{}", path, name);
+                    let datum = match (object.get(name), field.get("default")) {
+                        (Some(field_value), _) => self.build(field_value, field_type, &namespace, &field_path)?,
+                        // The default of a union field is a value of its first type.
+                        (None, Some(default)) => match field_type {
+                            Value::Array(branches) if !branches.is_empty() => AvroValue::Union(
+                                0,
+                                Box::new(self.build(default, &branches[0], &namespace, &field_path)?),
+                            ),
+                            _ => self.build(default, field_type, &namespace, &field_path)?,
+                        },
+                        (None, None) => return Err(format!("{}: missing required field", field_path)),
+                    };
+                    record.push((name.to_owned(), datum));
+                }
+                Ok(AvroValue::Record(record))
+            }
+            "enum" => {
+                let symbols: Vec<&str> = definition
+                    .get("symbols")
+                    .and_then(Value::as_array)
+                    .map(|symbols| symbols.iter().filter_map(Value::as_str).collect())
+                    .unwrap_or_default();
+                let symbol = value
+                    .as_str()
+                    .ok_or_else(|| format!("{}: expected an enum symbol, got {}", path, describe(value)))?;
+                let index = symbols
+                    .iter()
+                    .position(|candidate| *candidate == symbol)
+                    .ok_or_else(|| {
+                        format!(
+                            "{}: '{}' is not one of the symbols {}",
+                            path,
+                            symbol,
+                            symbols.join(", ")
+                        )
+                    })?;
+                Ok(AvroValue::Enum(index as u32, symbol.to_owned()))
+            }
+            "array" => {
+                let items = value
+                    .as_array()
+                    .ok_or_else(|| format!("{}: expected an array, got {}", path, describe(value)))?;
+                let item_type = definition.get("items").unwrap_or(&Value::Null);
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(index, item)| self.build(item, item_type, &namespace, &format!("{}[{}]", path, index)))
+                    .collect::<Result<_, _>>()
+                    .map(AvroValue::Array)
+            }
+            "map" => {
+                let entries = value
+                    .as_object()
+                    .ok_or_else(|| format!("{}: expected an object, got {}", path, describe(value)))?;
+                let value_type = definition.get("values").unwrap_or(&Value::Null);
+                entries
+                    .iter()
+                    .map(|(key, entry)| {
+                        let datum = self.build(entry, value_type, &namespace, &format!("{}.{}", path, key))?;
+                        Ok((key.to_owned(), datum))
+                    })
+                    .collect::<Result<_, String>>()
+                    .map(AvroValue::Map)
+            }
+            "fixed" => {
+                let size = definition.get("size").and_then(Value::as_u64).unwrap_or_default() as usize;
+                let bytes = value.as_str().map(latin1).ok_or_else(|| {
+                    format!("{}: expected a string of {} bytes, got {}", path, size, describe(value))
+                })??;
+                if bytes.len() != size {
+                    return Err(format!("{}: expected {} bytes, got {}", path, size, bytes.len()));
+                }
+                Ok(AvroValue::Fixed(size, bytes))
+            }
+            // A primitive written as an object, possibly with a logical type on top of it.
+            _ => {
+                let primitive_type = definition.get("type").unwrap_or(&Value::Null);
+                self.build(value, primitive_type, &namespace, path)
+            }
+        }
+    }
+
+    /// Name a union branch is selected by in the Avro JSON encoding.
+    fn type_name(&self, schema: &Value, namespace: &str) -> String {
+        match schema {
+            Value::String(name) if AVRO_PRIMITIVES.contains(&name.as_str()) => name.to_owned(),
+            Value::String(name) => full_name(name, namespace),
+            Value::Object(definition) => match (definition.get("name").and_then(Value::as_str), definition.get("type"))
+            {
+                (Some(name), _) => full_name(
+                    name,
+                    definition.get("namespace").and_then(Value::as_str).unwrap_or(namespace),
+                ),
+                (None, Some(inner)) => self.type_name(inner, namespace),
+                (None, None) => String::new(),
+            },
+            _ => String::new(),
+        }
+    }
+}
+
+fn primitive(value: &Value, name: &str, path: &str) -> Result<AvroValue, String> {
+    let datum = match (name, value) {
+        ("null", Value::Null) => Some(AvroValue::Null),
+        ("boolean", Value::Bool(value)) => Some(AvroValue::Boolean(*value)),
+        ("int", Value::Number(number)) => number
+            .as_i64()
+            .and_then(|number| i32::try_from(number).ok())
+            .map(AvroValue::Int),
+        ("long", Value::Number(number)) => number.as_i64().map(AvroValue::Long),
+        ("float", Value::Number(number)) => number.as_f64().map(|number| AvroValue::Float(number as f32)),
+        ("double", Value::Number(number)) => number.as_f64().map(AvroValue::Double),
+        ("bytes", Value::String(text)) => Some(AvroValue::Bytes(
+            latin1(text).map_err(|err| format!("{}: {}", path, err))?,
+        )),
+        ("string", Value::String(text)) => Some(AvroValue::String(text.to_owned())),
+        _ => None,
+    };
+    datum.ok_or_else(|| format!("{}: expected {} {}, got {}", path, article(name), name, describe(value)))
+}
+
+/// Bytes in the Avro JSON encoding, every character is one byte.
+fn latin1(text: &str) -> Result<Vec<u8>, String> {
+    text.chars()
+        .map(|c| {
+            u8::try_from(c).map_err(|_| format!("'{}' is not a byte, bytes are written as characters up to \\u00ff", c))
+        })
+        .collect()
+}
+
+fn article(name: &str) -> &'static str {
+    if name.starts_with(['a', 'e', 'i', 'o', 'u']) {
+        "an"
+    } else {
+        "a"
+    }
+}
+
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_owned(),
+        Value::Object(_) => "an object".to_owned(),
+        Value::Array(_) => "an array".to_owned(),
+        value => value.to_string(),
+    }
+}
+
+pub struct ProtobufSerializer {
+    message: MessageDescriptor,
+}
+impl ProtobufSerializer {
+    pub fn new(message: MessageDescriptor) -> Self {
+        Self { message }
+    }
+
+    pub fn message(&self) -> &MessageDescriptor {
+        &self.message
+    }
+}
+impl Serializer for ProtobufSerializer {
+    fn serialize(&self, value: &Value) -> Result<Vec<u8>, AppError> {
+        let mut track = serde_path_to_error::Track::new();
+        let message = DynamicMessage::deserialize(
+            self.message.to_owned(),
+            serde_path_to_error::Deserializer::new(value, &mut track),
+        )
+        .map_err(|err| {
+            let path = track.path().to_string();
+            let path = if path == "?" || path == "." {
+                "$".to_owned()
+            } else {
+                format!("$.{}", path)
+            };
+            AppError::invalid(format!("{}: {}", path, err))
+        })?;
+        Ok(message.encode_to_vec())
+    }
+}
+
+pub struct JsonSchemaSerializer {
+    validator: jsonschema::Validator,
+}
+impl JsonSchemaSerializer {
+    /// `references` are other schemas by the name `schema` refers to them with in `$ref`.
+    pub fn new(schema: &str, references: &[(String, String)]) -> Result<Self, AppError> {
+        let parse = |text: &str| {
+            serde_json::from_str::<Value>(text)
+                .map_err(|err| AppError::invalid(format!("Invalid JSON schema: {}", err)))
+        };
+        let mut options = jsonschema::options();
+        for (name, reference) in references {
+            let resource = jsonschema::Resource::from_contents(parse(reference)?)
+                .map_err(|err| AppError::invalid(format!("Invalid JSON schema '{}': {}", name, err)))?;
+            // Relative references resolve against the default base URI of a schema without `$id`.
+            let uri = match name.contains(':') {
+                true => name.to_owned(),
+                false => format!("json-schema:///{}", name),
+            };
+            options = options.with_resource(uri, resource);
+        }
+        let validator = options
+            .build(&parse(schema)?)
+            .map_err(|err| AppError::invalid(format!("Invalid JSON schema: {}", err)))?;
+        Ok(Self { validator })
+    }
+}
+impl Serializer for JsonSchemaSerializer {
+    fn serialize(&self, value: &Value) -> Result<Vec<u8>, AppError> {
+        if let Some(err) = self.validator.iter_errors(value).next() {
+            return Err(AppError::invalid(format!(
+                "{}: {}",
+                json_path(&err.instance_path.to_string()),
+                err
+            )));
+        }
+        serde_json::to_vec(value).map_err(|err| AppError::internal(err.to_string()))
+    }
+}
+
+/// `$.a[0].b` for the JSON pointer `/a/0/b`.
+fn json_path(pointer: &str) -> String {
+    pointer
+        .split('/')
+        .skip(1)
+        .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+        .fold("$".to_owned(), |path, segment| match segment.parse::<usize>() {
+            Ok(index) => format!("{}[{}]", path, index),
+            Err(_) => format!("{}.{}", path, segment),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use prost_reflect::{
+        prost_types::{
+            field_descriptor_proto::{Label, Type},
+            DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+        },
+        DescriptorPool,
+    };
+    use serde_json::{json, Value};
+
+    use super::{json_path, AvroSerializer, JsonSchemaSerializer, ProtobufSerializer, Serializer};
+    use crate::{
+        core::error::AppError,
+        kafka::deserializer::{AvroDeserializer, Deserializer},
+    };
+
+    fn address() -> Value {
+        json!({
+            "type": "record",
+            "name": "Address",
+            "namespace": "example.common",
+            "fields": [{"name": "zip", "type": "string"}]
+        })
+    }
+
+    fn order() -> Value {
+        json!({
+            "type": "record",
+            "name": "Order",
+            "namespace": "example",
+            "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "address", "type": "example.common.Address"},
+                {"name": "billing", "type": ["null", "example.common.Address"], "default": null},
+                {"name": "items", "type": {"type": "array", "items": "int"}},
+                {"name": "attributes", "type": {"type": "map", "values": "string"}, "default": {}},
+                {"name": "status", "type": {"type": "enum", "name": "Status", "symbols": ["OPEN", "CLOSED"]}}
+            ]
+        })
+    }
+
+    fn order_serializer() -> AvroSerializer {
+        AvroSerializer::with_references(&[&address().to_string(), &order().to_string()]).unwrap()
+    }
+
+    fn valid_order() -> Value {
+        json!({
+            "id": 7,
+            "address": {"zip": "1234"},
+            "items": [1, 2, 3],
+            "attributes": {"color": "red"},
+            "status": "OPEN"
+        })
+    }
+
+    fn error_message(result: Result<Vec<u8>, AppError>) -> String {
+        match result {
+            Err(AppError::Invalid(message)) => message,
+            Err(err) => panic!("expected an invalid value, got {:?}", err),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    fn with(field: &str, value: Value) -> Value {
+        let mut order = valid_order();
+        order[field] = value;
+        order
+    }
+
+    #[test]
+    fn avro_uses_named_types_of_references_and_defaults() {
+        let bytes = order_serializer().serialize(&valid_order()).unwrap();
+        let deserializer = AvroDeserializer::with_references(&[&address().to_string(), &order().to_string()]).unwrap();
+        let decoded = deserializer.deserialize(&bytes).unwrap();
+        assert_eq!(decoded["address"], json!({"zip": "1234"}));
+        assert_eq!(decoded["billing"], Value::Null);
+        assert_eq!(decoded["items"], json!([1, 2, 3]));
+        assert_eq!(decoded["status"], json!("OPEN"));
+
+        // Union values in the Avro JSON encoding, by full or short name.
+        for billing in [json!({"example.common.Address": {"zip": "9"}}), json!({"Address": {"zip": "9"}})] {
+            let bytes = order_serializer().serialize(&with("billing", billing)).unwrap();
+            assert_eq!(deserializer.deserialize(&bytes).unwrap()["billing"], json!({"zip": "9"}));
+        }
+    }
+
+    #[test]
+    fn avro_errors_point_at_the_failing_field() {
+        let serializer = order_serializer();
+        let cases = [
+            (with("address", json!({"zip": 1234})), "$.address.zip: expected a string, got 1234"),
+            (with("items", json!([1, 2, "three"])), "$.items[2]: expected an int, got \"three\""),
+            (with("attributes", json!({"color": false})), "$.attributes.color: expected a string, got false"),
+            (with("address", json!({"zip": "1", "street": "x"})), "$.address.street: not a field of the record"),
+            (with("address", json!({})), "$.address.zip: missing required field"),
+            (with("id", json!(1.5)), "$.id: expected a long, got 1.5"),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(error_message(serializer.serialize(&value)), expected);
+        }
+    }
+
+    #[test]
+    fn avro_optional_record_reports_the_record_error() {
+        let message = error_message(order_serializer().serialize(&with("billing", json!({"zip": 5}))));
+        assert_eq!(message, "$.billing.zip: expected a string, got 5");
+
+        let message = error_message(order_serializer().serialize(&with("billing", json!("home"))));
+        assert_eq!(message, "$.billing: expected an object, got \"home\"");
+    }
+
+    #[test]
+    fn avro_union_without_a_single_type_lists_the_branches() {
+        let schema = json!({
+            "type": "record",
+            "name": "Value",
+            "fields": [{"name": "amount", "type": ["null", "int", "string"]}]
+        });
+        let serializer = AvroSerializer::with_references(&[&schema.to_string()]).unwrap();
+        let message = error_message(serializer.serialize(&json!({"amount": true})));
+        assert_eq!(message, "$.amount: true matches none of the union types null, int, string");
+    }
+
+    #[test]
+    fn avro_enum_symbols_are_checked() {
+        let message = error_message(order_serializer().serialize(&with("status", json!("PENDING"))));
+        assert_eq!(message, "$.status: 'PENDING' is not one of the symbols OPEN, CLOSED");
+
+        let message = error_message(order_serializer().serialize(&with("status", json!(1))));
+        assert_eq!(message, "$.status: expected an enum symbol, got 1");
+    }
+
+    #[test]
+    fn avro_schema_needs_its_references() {
+        assert!(AvroSerializer::with_references(&[&order().to_string()]).is_err());
+    }
+
+    fn protobuf_serializer() -> ProtobufSerializer {
+        let field = |name: &str, number: i32, field_type: Type, type_name: Option<&str>, label: Label| {
+            FieldDescriptorProto {
+                name: Some(name.to_owned()),
+                number: Some(number),
+                label: Some(label as i32),
+                r#type: Some(field_type as i32),
+                type_name: type_name.map(str::to_owned),
+                json_name: Some(name.to_owned()),
+                ..Default::default()
+            }
+        };
+        let file = FileDescriptorProto {
+            name: Some("order.proto".to_owned()),
+            package: Some("example".to_owned()),
+            syntax: Some("proto3".to_owned()),
+            message_type: vec![
+                DescriptorProto {
+                    name: Some("Address".to_owned()),
+                    field: vec![field("zip", 1, Type::Int32, None, Label::Optional)],
+                    ..Default::default()
+                },
+                DescriptorProto {
+                    name: Some("Order".to_owned()),
+                    field: vec![
+                        field("id", 1, Type::Int64, None, Label::Optional),
+                        field("address", 2, Type::Message, Some(".example.Address"), Label::Optional),
+                        field("items", 3, Type::Message, Some(".example.Address"), Label::Repeated),
+                    ],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let pool = DescriptorPool::from_file_descriptor_set(FileDescriptorSet { file: vec![file] }).unwrap();
+        ProtobufSerializer::new(pool.get_message_by_name("example.Order").unwrap())
+    }
+
+    #[test]
+    fn protobuf_errors_point_at_the_failing_field() {
+        let serializer = protobuf_serializer();
+        assert!(serializer.serialize(&json!({"id": 1, "address": {"zip": 5}})).is_ok());
+
+        let message = error_message(serializer.serialize(&json!({"id": 1, "address": {"zip": "x"}})));
+        assert!(message.starts_with("$.address.zip: "), "{}", message);
+        let message = error_message(serializer.serialize(&json!({"items": [{"zip": 1}, {"zip": true}]})));
+        assert!(message.starts_with("$.items[1].zip: "), "{}", message);
+        let message = error_message(serializer.serialize(&json!([1])));
+        assert!(message.starts_with("$: "), "{}", message);
+    }
+
+    #[test]
+    fn json_schema_errors_point_at_the_failing_field() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "address": {"$ref": "address.json"},
+                "items": {"type": "array", "items": {"type": "integer"}}
+            }
+        });
+        let address = json!({"type": "object", "properties": {"zip": {"type": "string"}}});
+        let serializer =
+            JsonSchemaSerializer::new(&schema.to_string(), &[("address.json".to_owned(), address.to_string())])
+                .unwrap();
+
+        let valid = json!({"address": {"zip": "1"}, "items": [1]});
+        assert_eq!(serializer.serialize(&valid).unwrap(), serde_json::to_vec(&valid).unwrap());
+        let message = error_message(serializer.serialize(&json!({"address": {"zip": 1}})));
+        assert!(message.starts_with("$.address.zip: "), "{}", message);
+        let message = error_message(serializer.serialize(&json!({"items": [1, 2, "3"]})));
+        assert!(message.starts_with("$.items[2]: "), "{}", message);
+    }
+
+    #[test]
+    fn json_pointers_become_paths() {
+        assert_eq!(json_path(""), "$");
+        assert_eq!(json_path("/address/zip"), "$.address.zip");
+        assert_eq!(json_path("/items/2/name"), "$.items[2].name");
+        assert_eq!(json_path("/a~1b/c~0d"), "$.a/b.c~d");
+    }
+}
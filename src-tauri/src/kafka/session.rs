@@ -0,0 +1,320 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+use tokio::{
+    sync::{mpsc, watch},
+    time::{self, MissedTickBehavior},
+};
+
+use crate::core::error::AppError;
+
+use super::{
+    consumer::{ConsumerControl, FetchOffset, PartitionOffset, StopReason},
+    filter::MessageFilter,
+};
+
+/// How long ended sessions stay queryable.
+pub const SESSION_RETENTION: Duration = Duration::from_secs(10 * 60);
+/// How often `ConsumerRegistry::sweep` drops sessions past their retention.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum SessionKind {
+    Consumer,
+    Export,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum SessionState {
+    Running,
+    Paused,
+    Finished,
+    /// Ended by an error, see `ConsumerSession::error`.
+    Errored,
+}
+
+/// A consumer or export started from the UI, recorded when it starts and updated while it runs.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsumerSession {
+    /// Prefix of the events the session emits.
+    pub id: String,
+    pub kind: SessionKind,
+    pub cluster: String,
+    pub topic: String,
+    pub start: FetchOffset,
+    pub end: Option<FetchOffset>,
+    /// Partitions read from their own start, every partition from `start` when unset.
+    pub partitions: Option<HashMap<i32, PartitionOffset>>,
+    pub filter: Option<MessageFilter>,
+    /// Milliseconds since the epoch.
+    pub created_at: u128,
+    pub finished_at: Option<u128>,
+    /// Messages read so far, whether they passed the filter or not.
+    pub consumed: u64,
+    /// Next offset to read per partition.
+    pub offsets: HashMap<i32, i64>,
+    pub state: SessionState,
+    pub reason: Option<StopReason>,
+    pub error: Option<AppError>,
+}
+
+impl ConsumerSession {
+    pub fn new(id: String, kind: SessionKind, cluster: String, topic: String, start: FetchOffset) -> Self {
+        Self {
+            id,
+            kind,
+            cluster,
+            topic,
+            start,
+            end: None,
+            partitions: None,
+            filter: None,
+            created_at: now_millis(),
+            finished_at: None,
+            consumed: 0,
+            offsets: HashMap::new(),
+            state: SessionState::Running,
+            reason: None,
+            error: None,
+        }
+    }
+
+    fn has_ended(&self) -> bool {
+        matches!(self.state, SessionState::Finished | SessionState::Errored)
+    }
+}
+
+struct ActiveConsumer {
+    session: ConsumerSession,
+    /// Dropped once the session ended.
+    control: Option<mpsc::Sender<ConsumerControl>>,
+    /// Messages the UI acknowledged so far, for consumers emitting batches to it.
+    acknowledged: Option<watch::Sender<u64>>,
+}
+
+/// Every running consumer and export by id, ended ones are removed after `SESSION_RETENTION`.
+#[derive(Default)]
+pub struct ConsumerRegistry {
+    sessions: Mutex<HashMap<String, ActiveConsumer>>,
+}
+
+impl ConsumerRegistry {
+    pub fn start(
+        &self,
+        session: ConsumerSession,
+        control: mpsc::Sender<ConsumerControl>,
+        acknowledged: Option<watch::Sender<u64>>,
+    ) -> Result<(), AppError> {
+        let mut sessions = self.sessions.lock()?;
+        prune(&mut sessions, now_millis());
+        sessions.insert(
+            session.id.to_owned(),
+            ActiveConsumer {
+                session,
+                control: Some(control),
+                acknowledged,
+            },
+        );
+        Ok(())
+    }
+
+    /// All sessions, oldest first.
+    pub fn sessions(&self) -> Result<Vec<ConsumerSession>, AppError> {
+        let mut sessions = self.sessions.lock()?;
+        prune(&mut sessions, now_millis());
+        let mut list: Vec<ConsumerSession> = sessions.values().map(|active| active.session.to_owned()).collect();
+        list.sort_by_key(|session| session.created_at);
+        Ok(list)
+    }
+
+    pub fn session(&self, id: &str) -> Result<ConsumerSession, AppError> {
+        let mut sessions = self.sessions.lock()?;
+        prune(&mut sessions, now_millis());
+        sessions
+            .get(id)
+            .map(|active| active.session.to_owned())
+            .ok_or_else(|| unknown(id))
+    }
+
+    /// Channel to control the session through, `None` once it ended.
+    pub fn control(&self, id: &str) -> Result<Option<mpsc::Sender<ConsumerControl>>, AppError> {
+        let sessions = self.sessions.lock()?;
+        sessions.get(id).map(|active| active.control.clone()).ok_or_else(|| unknown(id))
+    }
+
+    /// Sessions that already ended are ignored, their last batches may be shown after they did.
+    pub fn acknowledge(&self, id: &str, count: u64) -> Result<(), AppError> {
+        let sessions = self.sessions.lock()?;
+        if let Some(acknowledged) = sessions.get(id).and_then(|active| active.acknowledged.as_ref()) {
+            acknowledged.send_modify(|acked| *acked += count);
+        }
+        Ok(())
+    }
+
+    /// Applies `change` to a session that has not ended yet.
+    pub fn update(&self, id: &str, change: impl FnOnce(&mut ConsumerSession)) {
+        if let Ok(mut sessions) = self.sessions.lock() {
+            if let Some(active) = sessions.get_mut(id).filter(|active| !active.session.has_ended()) {
+                change(&mut active.session);
+            }
+        }
+    }
+
+    /// Records how a session ended, it stays queryable for `SESSION_RETENTION`.
+    pub fn finish(&self, id: &str, reason: StopReason, error: Option<AppError>) {
+        if let Ok(mut sessions) = self.sessions.lock() {
+            if let Some(active) = sessions.get_mut(id) {
+                active.session.state = match error {
+                    Some(_) => SessionState::Errored,
+                    None => SessionState::Finished,
+                };
+                active.session.reason = Some(reason);
+                active.session.error = error;
+                active.session.finished_at = Some(now_millis());
+                active.control = None;
+                active.acknowledged = None;
+            }
+        }
+    }
+
+    /// Drops sessions past their retention every `SWEEP_INTERVAL`, runs for the lifetime of the app.
+    pub async fn sweep(&self) {
+        let mut interval = time::interval(SWEEP_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Ok(mut sessions) = self.sessions.lock() {
+                prune(&mut sessions, now_millis());
+            }
+        }
+    }
+
+    /// Stops every session reading from `cluster`.
+    pub fn stop_cluster(&self, cluster: &str) -> Result<(), AppError> {
+        let mut sessions = self.sessions.lock()?;
+        for active in sessions.values_mut().filter(|active| active.session.cluster == cluster) {
+            if let Some(control) = active.control.take() {
+                // A full channel holds an earlier command, the dropped sender ends the session after it.
+                let _ = control.try_send(ConsumerControl::Stop);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn prune(sessions: &mut HashMap<String, ActiveConsumer>, now: u128) {
+    let expired = now.saturating_sub(SESSION_RETENTION.as_millis());
+    sessions.retain(|_, active| active.session.finished_at.is_none_or(|finished_at| finished_at > expired));
+}
+
+fn unknown(id: &str) -> AppError {
+    AppError::invalid(format!("there is no such consumer running on channel: '{}'", id))
+}
+
+fn now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_else(|_err| Duration::from_millis(0))
+        .as_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::{mpsc, watch};
+
+    use super::{prune, ConsumerRegistry, ConsumerSession, SessionKind, SessionState, SESSION_RETENTION};
+    use crate::{
+        core::error::AppError,
+        kafka::consumer::{ConsumerControl, FetchOffset, StopReason},
+    };
+
+    fn start(registry: &ConsumerRegistry, id: &str, cluster: &str) -> mpsc::Receiver<ConsumerControl> {
+        let (sender, receiver) = mpsc::channel(1);
+        let session = ConsumerSession::new(
+            id.to_owned(),
+            SessionKind::Consumer,
+            cluster.to_owned(),
+            "orders".to_owned(),
+            FetchOffset::End,
+        );
+        registry.start(session, sender, None).unwrap();
+        receiver
+    }
+
+    #[test]
+    fn updates_stop_after_the_session_finished() {
+        let registry = ConsumerRegistry::default();
+        let _receiver = start(&registry, "c1", "local");
+        registry.update("c1", |session| {
+            session.consumed = 5;
+            session.state = SessionState::Paused;
+        });
+        assert_eq!(registry.session("c1").unwrap().state, SessionState::Paused);
+
+        registry.finish("c1", StopReason::Failed, Some(AppError::invalid("broken")));
+        registry.update("c1", |session| session.consumed = 99);
+        let session = registry.session("c1").unwrap();
+        assert_eq!((session.consumed, session.state), (5, SessionState::Errored));
+        assert!(session.finished_at.is_some());
+        assert!(registry.control("c1").unwrap().is_none());
+        assert!(registry.session("missing").is_err());
+    }
+
+    #[test]
+    fn acknowledgements_reach_running_sessions_only() {
+        let registry = ConsumerRegistry::default();
+        let (sender, _receiver) = mpsc::channel(1);
+        let (acknowledged, acknowledgements) = watch::channel(0u64);
+        let session = ConsumerSession::new(
+            "c1".to_owned(),
+            SessionKind::Consumer,
+            "local".to_owned(),
+            "orders".to_owned(),
+            FetchOffset::End,
+        );
+        registry.start(session, sender, Some(acknowledged)).unwrap();
+
+        registry.acknowledge("c1", 3).unwrap();
+        registry.acknowledge("c1", 2).unwrap();
+        assert_eq!(*acknowledgements.borrow(), 5);
+        registry.finish("c1", StopReason::Stopped, None);
+        registry.acknowledge("c1", 1).unwrap();
+        assert!(acknowledgements.has_changed().is_err(), "the sender is dropped once the session ended");
+    }
+
+    #[tokio::test]
+    async fn stop_cluster_stops_its_sessions_only() {
+        let registry = ConsumerRegistry::default();
+        let mut local = start(&registry, "c1", "local");
+        let mut other = start(&registry, "c2", "other");
+
+        registry.stop_cluster("local").unwrap();
+        assert!(matches!(local.recv().await, Some(ConsumerControl::Stop)));
+        assert!(local.recv().await.is_none(), "the control channel is closed after the stop");
+        assert!(registry.control("c1").unwrap().is_none());
+        assert!(other.try_recv().is_err());
+        assert!(registry.control("c2").unwrap().is_some());
+    }
+
+    #[test]
+    fn finished_sessions_are_kept_for_the_retention() {
+        let registry = ConsumerRegistry::default();
+        let _running = start(&registry, "running", "local");
+        let _finished = start(&registry, "finished", "local");
+        registry.finish("finished", StopReason::EndOffsets, None);
+        let finished_at = registry.session("finished").unwrap().finished_at.unwrap();
+        let retention = SESSION_RETENTION.as_millis();
+
+        let mut sessions = registry.sessions.lock().unwrap();
+        prune(&mut sessions, finished_at + retention - 1);
+        assert!(sessions.contains_key("finished"));
+        prune(&mut sessions, finished_at + retention + 1);
+        assert!(!sessions.contains_key("finished"));
+        assert!(sessions.contains_key("running"), "running sessions never expire");
+    }
+}
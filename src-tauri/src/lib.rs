@@ -3,6 +3,8 @@
 mod core;
 mod kafka;
 
+use tauri::Manager;
+
 use crate::core::{
     commands,
     config::ApplicationState,
@@ -22,15 +24,59 @@ pub fn run() {
         
     builder.plugin(tauri_plugin_theme::init(ctx.config_mut()))
         .plugin(tauri_plugin_shell::init())
-        .manage(ApplicationState::load())
+        .setup(|app| {
+            let config_dir = app.path().app_config_dir()?;
+            app.manage(ApplicationState::load(&config_dir));
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                handle.state::<ApplicationState>().active_consumers.sweep().await;
+            });
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             commands::get_current_cluster,
+            commands::list_clusters,
+            commands::set_current_cluster,
+            commands::add_cluster,
+            commands::update_cluster,
+            commands::rename_cluster,
+            commands::delete_cluster,
+            commands::test_cluster_connection,
+            commands::import_cluster_properties,
+            commands::export_cluster_properties,
+            commands::get_secrets_status,
+            commands::unlock_secrets,
+            commands::lock_secrets,
+            commands::change_secrets_passphrase,
+            commands::store_secret,
+            commands::delete_secret,
             commands::get_topics,
+            commands::refresh_metadata,
+            commands::set_metadata_ttl,
             commands::fetch_topic_configs,
             commands::alter_topic_configs,
+            commands::list_subjects,
+            commands::list_subject_versions,
+            commands::get_subject_schema,
+            commands::register_schema,
+            commands::check_schema_compatibility,
+            commands::get_compatibility_level,
+            commands::set_compatibility_level,
+            commands::delete_schema_version,
+            commands::diff_schema_versions,
             commands::get_all_active_consumers,
+            commands::get_consumer_session,
             commands::consume_topic_by_timestamp,
+            commands::render_message_bytes,
+            commands::export_topic_range,
+            commands::produce_message,
+            commands::produce_from_file,
             commands::stop_consumer,
+            commands::pause_consumer,
+            commands::resume_consumer,
+            commands::seek_consumer,
+            commands::change_consumer_filter,
+            commands::acknowledge_messages,
             commands::create_topic,
             commands::delete_topic,
             commands::get_groups,
@@ -1,5 +1,38 @@
 import { invoke } from "@tauri-apps/api/core";
 
+// Errors returned by every command
+export type ErrorResource =
+  | { type: "Cluster"; content: string }
+  | { type: "Topic"; content: string }
+  | { type: "Partition"; content: { topic: string; partition: number } }
+  | { type: "Group"; content: string };
+export type AppError =
+  | {
+      type: "Kafka";
+      content: { code: string | null; message: string; resource: ErrorResource | null; retryable: boolean };
+    }
+  | { type: "SchemaRegistry"; content: { status: number | null; code: number | null; message: string } }
+  | { type: "Invalid"; content: string }
+  | { type: "SecretsLocked" }
+  | { type: "Io"; content: string }
+  | { type: "Internal"; content: string };
+export function errorMessage(err: unknown): string {
+  const error = err as AppError;
+  switch (error?.type) {
+    case "Kafka":
+    case "SchemaRegistry":
+      return error.content.message;
+    case "SecretsLocked":
+      return "Secret store is locked, unlock it with the master passphrase first";
+    case "Invalid":
+    case "Io":
+    case "Internal":
+      return error.content;
+    default:
+      return String(err);
+  }
+}
+
 // Kafka Admin
 export type CreateTopicRequest = {
   topic: string;
@@ -46,6 +79,8 @@ export type PartitionInfo = {
 export type TopicInfo = {
   name: string;
   partitions: PartitionInfo[];
+  // Registry subjects named `<topic>-key` and `<topic>-value`, when they exist.
+  subjects: { key?: string; value?: string };
 };
 
 export type BrokerInfo = {
@@ -62,6 +97,80 @@ export type ClusterMetadata = {
 export function getClusterMetadata(): Promise<ClusterMetadata> {
   return invoke<ClusterMetadata>("get_topics");
 }
+// Emitted on `metadata_changed` when a fetch differs from the cached metadata of `cluster`.
+export type MetadataChanges = {
+  cluster: string;
+  addedTopics: TopicInfo[];
+  removedTopics: string[];
+  changedTopics: TopicInfo[];
+  // Only set when brokers joined or left.
+  brokers?: BrokerInfo[];
+};
+
+// Schema Registry
+export type SchemaType = "AVRO" | "PROTOBUF" | "JSON";
+export type SchemaReference = { name: string; subject: string; version: number };
+export type SchemaDefinition = { schema: string; schemaType?: SchemaType; references?: SchemaReference[] };
+export type RegisteredSchema = {
+  subject?: string;
+  id?: number;
+  version?: number;
+  schema: string;
+  schemaType: SchemaType;
+  references: SchemaReference[];
+};
+export type CompatibilityLevel =
+  | "BACKWARD"
+  | "BACKWARD_TRANSITIVE"
+  | "FORWARD"
+  | "FORWARD_TRANSITIVE"
+  | "FULL"
+  | "FULL_TRANSITIVE"
+  | "NONE";
+export type CompatibilityCheck = { isCompatible: boolean; messages: string[] };
+export type SchemaDiff = {
+  from: RegisteredSchema;
+  to: RegisteredSchema;
+  lines: { change: "Equal" | "Insert" | "Delete"; text: string }[];
+};
+
+export function listSubjects(deleted?: boolean) {
+  return invoke<string[]>("list_subjects", { deleted });
+}
+
+export function listSubjectVersions(subject: string, deleted?: boolean) {
+  return invoke<number[]>("list_subject_versions", { subject, deleted });
+}
+
+// `version` is a version number or "latest", the default.
+export function getSubjectSchema(subject: string, version?: string) {
+  return invoke<RegisteredSchema>("get_subject_schema", { subject, version });
+}
+
+export function registerSchema(subject: string, schema: SchemaDefinition) {
+  return invoke<number>("register_schema", { subject, schema });
+}
+
+export function checkSchemaCompatibility(subject: string, schema: SchemaDefinition, version?: string) {
+  return invoke<CompatibilityCheck>("check_schema_compatibility", { subject, schema, version });
+}
+
+// Without a subject the global level is used.
+export function getCompatibilityLevel(subject?: string) {
+  return invoke<CompatibilityLevel>("get_compatibility_level", { subject });
+}
+
+export function setCompatibilityLevel(level: CompatibilityLevel, subject?: string) {
+  return invoke<CompatibilityLevel>("set_compatibility_level", { subject, level });
+}
+
+export function deleteSchemaVersion(subject: string, version: string, permanent?: boolean) {
+  return invoke<number>("delete_schema_version", { subject, version, permanent });
+}
+
+export function diffSchemaVersions(subject: string, from: string, to: string) {
+  return invoke<SchemaDiff>("diff_schema_versions", { subject, from, to });
+}
 
 export type TopicGroupOffsets = {
   topic: string;
@@ -111,21 +220,149 @@ export function deleteConsumerGroup(group: string): Promise<string> {
 }
 
 // Consumers
+export type DisplayFormat = "utf8" | "hex" | "base64" | "hexdump";
+// `raw` is base64, `text` is `raw` in the display format the consumer was started with.
+export type MessageBytes = { raw: string | null; encoding: "Null" | "Utf8" | "Binary"; text: string | null };
+// `value` is set when the topic has a key or value format, `error` when it did not match the bytes.
+export type Decoded = MessageBytes & { value: unknown; error: AppError | null };
+export type DataFormat =
+  | { type: "String" | "Json" | "MessagePack" | "SchemaRegistry" | "Int" | "Long" | "Uuid" }
+  | { type: "Avro"; content: { schema: string } }
+  | { type: "Protobuf"; content: { descriptor_set: string; message: string } };
+export type TopicFormats = { key?: DataFormat; value?: DataFormat };
 export type MessageEnvelope = {
-  key: string;
+  key: Decoded;
   offset: number;
   partition: number;
   timestamp: number;
-  payload: string;
-  headers: Record<string, string>;
+  payload: Decoded;
+  headers: { key: string; value: MessageBytes }[];
 };
 
+// Emitted on `<consumerId>/error`, a stopped consumer also emits its end of stream.
+export type ConsumerError = { error: AppError; stopped: boolean };
+// Emitted on `<consumerId>/progress`, also while no message passes the filter.
+export type ScanProgress = {
+  scanned: number;
+  matched: number;
+  dropped: number;
+  pending: number;
+  paused: boolean;
+  messagesPerSecond: number;
+  bytesPerSecond: number;
+  offsets: Record<number, number>;
+  lag: Record<number, number>;
+};
+// Messages arrive in batches, each has to be acknowledged with `acknowledgeMessages` once shown.
+export type DeliveryOptions = {
+  batchSize?: number;
+  batchWindowMs?: number;
+  maxPending?: number;
+  dropWhenFull?: boolean;
+};
+export type StopConditions = {
+  maxMessages?: number;
+  endOfPartitions?: boolean;
+  maxDurationMs?: number;
+  idleTimeoutMs?: number;
+  maxBytes?: number;
+};
+export type StopReason =
+  | "Stopped"
+  | "EndOffsets"
+  | "MaxMessages"
+  | "EndOfPartitions"
+  | "MaxDuration"
+  | "IdleTimeout"
+  | "MaxBytes"
+  | "Failed";
+// Emitted on `<consumer id>/done` before the end of stream.
+export type ConsumerSummary = {
+  reason: StopReason;
+  scanned: number;
+  matched: number;
+  bytes: number;
+  dropped: number;
+  offsets: Record<number, number>;
+  elapsedMs: number;
+};
+
+export type FilterTarget = { type: "Key" } | { type: "Value" } | { type: "Header"; content: string | null };
+export type Comparison = "Exists" | "Eq" | "Ne" | "Lt" | "Lte" | "Gt" | "Gte" | "Contains";
+export type Range = { min?: number; max?: number };
+export type MessageFilter =
+  | { type: "All" | "Any"; content: MessageFilter[] }
+  | { type: "Not"; content: MessageFilter }
+  | { type: "Contains"; content: { target: FilterTarget; text: string; ignore_case?: boolean } }
+  | { type: "Regex"; content: { target: FilterTarget; pattern: string } }
+  | { type: "JsonPath"; content: { target: FilterTarget; path: string; comparison: Comparison; value?: unknown } }
+  | { type: "Partition" | "Offset" | "Timestamp"; content: Range };
+
 export type JsonMessageEnvelope = MessageEnvelope & { payloadJson: Record<string, unknown> | null };
 export type FetchOffset = { type: "Beginning" } | { type: "End" } | { type: "Timestamp"; content: number };
-export function consumeTopicBetweenOffsets(topic: string, start: FetchOffset, end?: FetchOffset) {
-  return invoke<[string, Record<string, [number, number][]>]>("consume_topic_by_timestamp", { topic, start, end });
+export type PartitionOffset =
+  | { type: "Beginning" }
+  | { type: "End" }
+  | { type: "Offset" | "Tail" | "Timestamp"; content: number }
+  | { type: "Committed"; content: string };
+// `formats` are remembered for the topic, without them the remembered ones are used.
+// Only messages passing `filter` are emitted. With `partitions` only those are read, each from its own offset.
+// The consumer also ends at the first of the `stop` conditions reached.
+export function consumeTopicBetweenOffsets(topic: string, start: FetchOffset, end?: FetchOffset, display?: DisplayFormat, formats?: TopicFormats, filter?: MessageFilter, partitions?: Record<number, PartitionOffset>, stop?: StopConditions, delivery?: DeliveryOptions) {
+  return invoke<[string, Record<string, [number, number][]>]>("consume_topic_by_timestamp", { topic, start, end, partitions, display, formats, filter, stop, delivery });
+}
+
+export function renderMessageBytes(bytes: MessageBytes, format: DisplayFormat) {
+  return invoke<string | null>("render_message_bytes", { bytes, format });
 }
 
 export function stopConsumer(consumerId: string) {
   return invoke<void>("stop_consumer", { consumerId });
 }
+
+export type ConsumerSession = {
+  id: string;
+  kind: "Consumer" | "Export";
+  cluster: string;
+  topic: string;
+  start: FetchOffset;
+  end: FetchOffset | null;
+  partitions: Record<number, PartitionOffset> | null;
+  filter: MessageFilter | null;
+  createdAt: number;
+  finishedAt: number | null;
+  consumed: number;
+  offsets: Record<number, number>;
+  state: "Running" | "Paused" | "Finished" | "Errored";
+  reason: StopReason | null;
+  error: AppError | null;
+};
+// Running sessions and the ones that ended in the last minutes.
+export function getConsumerSessions() {
+  return invoke<ConsumerSession[]>("get_all_active_consumers");
+}
+
+export function getConsumerSession(consumerId: string) {
+  return invoke<ConsumerSession>("get_consumer_session", { consumerId });
+}
+
+export function pauseConsumer(consumerId: string) {
+  return invoke<void>("pause_consumer", { consumerId });
+}
+
+export function resumeConsumer(consumerId: string) {
+  return invoke<void>("resume_consumer", { consumerId });
+}
+
+// Resolves to the offsets the partitions continue from.
+export function seekConsumer(consumerId: string, partitions: Record<number, PartitionOffset>) {
+  return invoke<Record<number, number>>("seek_consumer", { consumerId, partitions });
+}
+
+export function changeConsumerFilter(consumerId: string, filter?: MessageFilter) {
+  return invoke<void>("change_consumer_filter", { consumerId, filter });
+}
+
+export function acknowledgeMessages(consumerId: string, count: number) {
+  return invoke<void>("acknowledge_messages", { consumerId, count });
+}
@@ -1,8 +1,10 @@
-import { getClusterMetadata } from "@/lib/kafka";
+import { errorMessage, getClusterMetadata, MetadataChanges } from "@/lib/kafka";
+import { getCurrentWebviewWindow } from "@tauri-apps/api/webviewWindow";
 import { defineStore } from "pinia";
 import { ref } from "vue";
 import { useTopics } from "./topics";
 import { useBrokers } from "./brokers";
+import { useAppConfig } from "./configuration";
 
 const DEFAULT_TTL = 5 * 60 * 1000; // 5 mins
 
@@ -17,7 +19,7 @@ export const useClusterMetadata = defineStore("metadata", () => {
     isLoading.value = true;
     return getClusterMetadata()
       .catch(err => {
-        lastError.value = err;
+        lastError.value = errorMessage(err);
         throw err;
       })
       .then(data => {
@@ -35,6 +37,22 @@ export const useClusterMetadata = defineStore("metadata", () => {
       });
   }
 
+  // Any refetch of the metadata, from this window or another, only sends what changed.
+  function applyChanges(changes: MetadataChanges) {
+    const { cluster } = useAppConfig();
+    if (lastLoadTime.value === -Infinity || cluster?.name !== changes.cluster) {
+      return;
+    }
+    const { applyTopicChanges } = useTopics();
+    const { setBrokers } = useBrokers();
+    applyTopicChanges(changes.addedTopics, changes.removedTopics, changes.changedTopics);
+    if (changes.brokers) {
+      setBrokers(changes.brokers);
+    }
+    lastLoadTime.value = Date.now();
+  }
+  getCurrentWebviewWindow().listen<MetadataChanges>("metadata_changed", event => applyChanges(event.payload));
+
   function loadMetadata(ttl = DEFAULT_TTL) {
     const metadataAge = Date.now() - lastLoadTime.value;
     console.log(`Load Metadata, age: ${metadataAge}, ttl: ${ttl}, Fetch: ${metadataAge > ttl}`);
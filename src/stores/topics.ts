@@ -119,6 +119,20 @@ export const useTopics = defineStore("topics", () => {
     fetchAllTopicConfigs();
   }
 
+  function applyTopicChanges(added: TopicInfo[], removed: string[], changed: TopicInfo[]) {
+    const changedTopics = new Map(changed.map(topic => [topic.name, topic]));
+    allTopics.value = allTopics.value
+      .filter(topic => !removed.includes(topic.name))
+      // Subjects are linked when the list is requested, changes come without them.
+      .map(topic => {
+        const update = changedTopics.get(topic.name);
+        return update ? { ...update, subjects: topic.subjects } : topic;
+      })
+      .concat(added);
+    removed.forEach(topic => delete configEntries.value[topic]);
+    added.forEach(topic => fetchTopicConfigs(topic.name));
+  }
+
   return {
     allTopics,
     createTopic,
@@ -128,6 +142,7 @@ export const useTopics = defineStore("topics", () => {
     isValidTopic,
     filterTopics,
     setTopicsMetadata,
+    applyTopicChanges,
     configLoading,
     topicConfigs,
     nonDefaultTopicConfigs,